@@ -27,29 +27,108 @@
 
 use slog::{Drain, Level, OwnedKVList, Record};
 use std::{fmt, io};
-use std::sync::Mutex;
+use std::sync::{mpsc, Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::thread;
 use std::cell::RefCell;
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
 use std::path::{Path, PathBuf};
-use std::net::SocketAddr;
-use std::io::{Error, ErrorKind};
+use std::net::{SocketAddr, TcpStream, UdpSocket};
+use std::time::{Duration, Instant};
+use std::io::Error;
 
 use slog::KV;
 
+mod limit;
+
+pub use limit::RateLimited;
 pub use syslog::Facility;
 
+/// Commonly used types, for glob importing
+///
+/// ```
+/// use slog_syslog::prelude::*;
+///
+/// let _builder = SyslogBuilder::new().facility(Facility::LOG_USER);
+/// ```
+///
+/// Re-exports [`SyslogBuilder`], [`Streamer3164`], [`AsyncStreamer3164`],
+/// [`OverflowPolicy`], [`RateLimited`], [`Format3164`], [`Limits`],
+/// [`LastError`], [`LevelHandle`], [`TcpFraming`], [`TransportSpec`] and
+/// [`Facility`].
+pub mod prelude {
+    pub use crate::{
+        AsyncStreamer3164, Facility, Format3164, LastError, LevelHandle, Limits, OverflowPolicy, RateLimited,
+        Streamer3164, SyslogBuilder, TcpFraming, TransportSpec,
+    };
+}
+
 thread_local! {
-    static TL_BUF: RefCell<Vec<u8>> = RefCell::new(Vec::with_capacity(128))
+    static TL_BUF: RefCell<String> = RefCell::new(String::with_capacity(128))
 }
 
 type SysLogger = syslog::Logger<syslog::LoggerBackend, syslog::Formatter3164>;
 
+/// Owned copy of a `syslog::Error` and its chain of causes
+///
+/// `syslog::Error` is not `Sync`, so it can't be wrapped in an
+/// `io::Error` directly; this keeps its messages and `source()` chain.
+#[derive(Debug)]
+struct SyslogError {
+    message: String,
+    source: Option<Box<SyslogError>>,
+}
+
+impl SyslogError {
+    fn new(e: &dyn std::error::Error) -> Self {
+        SyslogError {
+            message: e.to_string(),
+            source: e.source().map(|source| Box::new(SyslogError::new(source))),
+        }
+    }
+}
+
+impl fmt::Display for SyslogError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for SyslogError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.source.as_deref().map(|e| e as &(dyn std::error::Error + 'static))
+    }
+}
+
 #[inline]
 fn handle_syslog_error(e: syslog::Error) -> io::Error
 {
-    Error::new(ErrorKind::Other, e.to_string())
+    Error::new(syslog_error_kind(&e), SyslogError::new(&e))
+}
+
+/// Kind of the first `io::Error` in the chain of `e`
+///
+/// Falls back to `Other`, or `Unsupported` on platforms the `syslog`
+/// crate doesn't support.
+fn syslog_error_kind(e: &syslog::Error) -> io::ErrorKind {
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(e);
+    while let Some(err) = source {
+        if let Some(io) = err.downcast_ref::<io::Error>() {
+            return io.kind();
+        }
+        if let Some(syslog::ErrorKind::Io(io)) = err.downcast_ref::<syslog::Error>().map(|e| e.kind()) {
+            return io.kind();
+        }
+        source = err.source();
+    }
+    match *e.kind() {
+        syslog::ErrorKind::UnsupportedPlatform => io::ErrorKind::Unsupported,
+        _ => io::ErrorKind::Other,
+    }
 }
 
-fn log_with_level(level: slog::Level, mut io: std::sync::MutexGuard<Box<SysLogger>>, buf: &str) -> io::Result<()> {
+fn log_with_level(level: slog::Level, io: &mut SysLogger, buf: &str) -> io::Result<()> {
     let err = match level {
         Level::Critical => io.crit(&buf),
         Level::Error => io.err(&buf),
@@ -61,6 +140,30 @@ fn log_with_level(level: slog::Level, mut io: std::sync::MutexGuard<Box<SysLogge
     err.map_err(handle_syslog_error)
 }
 
+/// Numeric syslog severity `log_with_level` uses for `level`
+fn severity(level: slog::Level) -> u8 {
+    match level {
+        Level::Critical => 2,
+        Level::Error => 3,
+        Level::Warning => 4,
+        Level::Info => 5,
+        Level::Debug => 6,
+        Level::Trace => 7,
+    }
+}
+
+/// Syslog severity `log_with_level` uses for `level`
+fn syslog_severity(level: slog::Level) -> syslog::Severity {
+    match level {
+        Level::Critical => syslog::Severity::LOG_CRIT,
+        Level::Error => syslog::Severity::LOG_ERR,
+        Level::Warning => syslog::Severity::LOG_WARNING,
+        Level::Info => syslog::Severity::LOG_NOTICE,
+        Level::Debug => syslog::Severity::LOG_INFO,
+        Level::Trace => syslog::Severity::LOG_DEBUG,
+    }
+}
+
 /// Create a formatter with runtime metadata filled in. 
 ///
 /// This follows ``get_process_info()`` in the syslog crate to some extent
@@ -70,7 +173,7 @@ fn syslog_format3164(facility: syslog::Facility, hostname: Option<String>) -> sy
         .unwrap_or_else(|_| PathBuf::new());
     let process = path.file_name()
         .map(|file| file.to_string_lossy().into_owned())
-        .unwrap_or_else(|| String::new());
+        .unwrap_or_default();
 
     syslog::Formatter3164 {
         facility,
@@ -80,17 +183,352 @@ fn syslog_format3164(facility: syslog::Facility, hostname: Option<String>) -> sy
     }
 }
 
+static KERN_WARNED: AtomicBool = AtomicBool::new(false);
+
+/// Warn once per process that userspace usually can't log to `LOG_KERN`.
+fn warn_if_kern(facility: syslog::Facility) {
+    if matches!(facility, Facility::LOG_KERN) && !KERN_WARNED.swap(true, Ordering::Relaxed) {
+        eprintln!(
+            "slog-syslog: logging with facility LOG_KERN; syslog daemons usually \
+             drop or reclassify kern messages from non-kernel processes"
+        );
+    }
+}
+
+/// Create a UDP logger whose socket is `connect()`ed to `host`.
+///
+/// A connected socket reports ICMP port-unreachable replies as an error
+/// on a following send, instead of silently dropping the packets.
+///
+/// The `syslog` crate still sends with `send_to(host)`. Linux accepts
+/// that on a connected socket, but macOS and the BSDs fail every send
+/// with `EISCONN`, so this is limited to Linux.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn udp_connected(format: syslog::Formatter3164, local: SocketAddr, host: SocketAddr) -> io::Result<SysLogger> {
+    let socket = UdpSocket::bind(local)?;
+    socket.connect(host)?;
+    Ok(syslog::Logger::new(syslog::LoggerBackend::Udp(socket, host), format))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn udp_connected(_format: syslog::Formatter3164, _local: SocketAddr, _host: SocketAddr) -> io::Result<SysLogger> {
+    Err(Error::new(io::ErrorKind::Unsupported, "connected UDP sockets are only supported on Linux"))
+}
+
+/// Console written to by `SyslogBuilder::console_fallback`
+const CONSOLE: &str = "/dev/console";
+
+/// Best-effort write of `msg` to the console at `path`
+fn write_console(path: &Path, msg: &str) {
+    use std::io::Write;
+
+    if let Ok(mut console) = std::fs::OpenOptions::new().write(true).open(path) {
+        let _ = writeln!(console, "{}", msg);
+    }
+}
+
+/// Shared, changeable logging level of a `Streamer3164`
+///
+/// Records less severe than the level are dropped. Changes made with
+/// `set` apply to every record logged afterwards, from any thread.
+/// The level is an independent value with no other data published
+/// alongside it, so relaxed atomic ordering is enough.
+#[derive(Clone, Debug)]
+pub struct LevelHandle(Arc<AtomicUsize>);
+
+impl LevelHandle {
+    fn new(level: Level) -> Self {
+        LevelHandle(Arc::new(AtomicUsize::new(level.as_usize())))
+    }
+
+    /// Current level
+    pub fn get(&self) -> Level {
+        Level::from_usize(self.0.load(Ordering::Relaxed)).unwrap_or(Level::Trace)
+    }
+
+    /// Change the level
+    pub fn set(&self, level: Level) {
+        self.0.store(level.as_usize(), Ordering::Relaxed);
+    }
+}
+
+/// Keeps one in `keep_one_in` records at or below `level`
+struct Sampling {
+    level: Level,
+    keep_one_in: u32,
+    counter: AtomicU32,
+}
+
+impl Sampling {
+    fn keep(&self, level: Level) -> bool {
+        if self.keep_one_in <= 1 || !self.level.is_at_least(level) {
+            return true;
+        }
+        self.counter.fetch_add(1, Ordering::Relaxed) % self.keep_one_in == 0
+    }
+}
+
 /// Drain formatting records and writing them to a syslog ``Logger`
 ///
-/// Uses mutex to serialize writes.
-/// TODO: Add one that does not serialize?
+/// Uses mutex to serialize writes. See `AsyncStreamer3164` to write
+/// from a separate thread instead.
 pub struct Streamer3164 {
     io: Mutex<Box<SysLogger>>,
     format: Format3164,
-    level: Level,
+    level: LevelHandle,
+    sampling: Option<Sampling>,
+    buffer_capacity: usize,
+    console_fallback: Option<Level>,
+    console: PathBuf,
+    catch_panics: bool,
+    last_error: Mutex<Option<LastError>>,
+    sidecar: Option<Sidecar>,
+    reconnect: Option<Reconnect>,
+    framer: Option<Framer>,
+    dedup: Option<Dedup>,
+}
+
+/// Suppresses messages repeating the previous one
+struct Dedup {
+    timeout: Duration,
+    last: Mutex<Repeated>,
+}
+
+/// The last message sent, and how often it was repeated since
+struct Repeated {
+    level: Option<Level>,
+    message: String,
+    count: u64,
+    since: Instant,
+}
+
+impl Dedup {
+    fn new(timeout: Duration) -> Self {
+        Dedup {
+            timeout,
+            last: Mutex::new(Repeated {
+                level: None,
+                message: String::new(),
+                count: 0,
+                since: Instant::now(),
+            }),
+        }
+    }
+}
+
+/// TCP server to connect to again when a write fails
+struct Reconnect {
+    facility: syslog::Facility,
+    server: SocketAddr,
+    hostname: String,
+    options: TcpOptions,
+}
+
+/// Connect timeout of reconnection attempts, unless one is configured
+const RECONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Longest wait between reconnection attempts, unless the initial
+/// backoff is longer
+const MAX_RECONNECT_BACKOFF: Duration = Duration::from_secs(30);
+
+impl Reconnect {
+    /// Replace `io` with a new connection, backing off between attempts
+    ///
+    /// Returns a handle to the new socket.
+    fn run(&self, io: &mut SysLogger) -> io::Result<TcpStream> {
+        let mut backoff = self.options.reconnect_backoff;
+        let max_backoff = backoff.max(MAX_RECONNECT_BACKOFF);
+        let mut res = Err(Error::other("TCP reconnection is disabled"));
+        for attempt in 0..self.options.reconnect_attempts {
+            if attempt > 0 {
+                std::thread::sleep(backoff);
+                backoff = backoff.saturating_mul(2).min(max_backoff);
+            }
+            let format = syslog_format3164(self.facility, Some(self.hostname.clone()));
+            res = tcp_connect(format, self.server, &self.options);
+            if let Ok((log, stream)) = res {
+                *io = log;
+                return Ok(stream);
+            }
+        }
+        res.map(|(_, stream)| stream)
+    }
+}
+
+/// How messages are delimited on a TCP stream, as in RFC 6587
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TcpFraming {
+    /// Every message is followed by a LF
+    ///
+    /// A LF inside a message splits it in two on the receiving end;
+    /// see `Format3164::with_escaped_control_chars`.
+    NonTransparent,
+    /// Every message is preceded by its length in bytes and a space
+    ///
+    /// Messages may contain any bytes, including LF.
+    OctetCounting,
+}
+
+/// Socket written to by a `Framer`
+enum FramedStream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(std::os::unix::net::UnixStream),
+}
+
+impl io::Write for FramedStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self {
+            FramedStream::Tcp(ref mut stream) => stream.write(buf),
+            #[cfg(unix)]
+            FramedStream::Unix(ref mut stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            FramedStream::Tcp(ref mut stream) => stream.flush(),
+            #[cfg(unix)]
+            FramedStream::Unix(ref mut stream) => stream.flush(),
+        }
+    }
+}
+
+/// Writes messages sent over a stream socket, with the framing around
+/// them
+///
+/// The `syslog` crate doesn't frame messages on TCP, and ends them with a
+/// NUL on unix stream sockets, so the framer formats them itself and
+/// writes each frame in one go to a second handle of the socket, while
+/// `Streamer3164::io` is locked.
+struct Framer {
+    framing: TcpFraming,
+    format: syslog::Formatter3164,
+    stream: Mutex<FramedStream>,
+}
+
+impl Framer {
+    fn new(framing: TcpFraming, format: syslog::Formatter3164, stream: FramedStream) -> Self {
+        Framer {
+            framing,
+            format,
+            stream: Mutex::new(stream),
+        }
+    }
+
+    /// Send the message `buf` framed, at `level`
+    fn send(&self, level: Level, buf: &str) -> io::Result<()> {
+        use std::io::Write;
+        use syslog::LogFormat;
+
+        let mut message = Vec::new();
+        self.format
+            .format(&mut message, syslog_severity(level), buf)
+            .map_err(handle_syslog_error)?;
+        let frame = match self.framing {
+            TcpFraming::NonTransparent => {
+                message.push(b'\n');
+                message
+            }
+            TcpFraming::OctetCounting => {
+                let mut frame = format!("{} ", message.len()).into_bytes();
+                frame.extend_from_slice(&message);
+                frame
+            }
+        };
+        let mut stream = self.stream.lock().map_err(|_| Error::other("locking error"))?;
+        stream.write_all(&frame)?;
+        stream.flush()
+    }
+
+    fn replace_stream(&self, new: FramedStream) {
+        match self.stream.lock() {
+            Ok(mut stream) => *stream = new,
+            Err(poisoned) => *poisoned.into_inner() = new,
+        }
+    }
+}
+
+/// Writer receiving a JSON line for every record sent to syslog
+struct Sidecar {
+    io: Mutex<Box<dyn io::Write + Send>>,
+    facility: syslog::Facility,
+}
+
+impl Sidecar {
+    /// Build the JSON line for `record`
+    fn line(&self, record: &Record, logger_kv: &OwnedKVList) -> slog::Result<String> {
+        let mut line = String::with_capacity(128);
+        line.push_str("{\"msg\":");
+        write_json_string(&mut line, &record.msg().to_string());
+        let priority = self.facility as u8 | severity(record.level());
+        line.push_str(",\"priority\":");
+        line.push_str(&priority.to_string());
+        let mut ser = JsonSerializer { io: &mut line };
+        logger_kv.serialize(record, &mut ser)?;
+        record.kv().serialize(record, &mut ser)?;
+        line.push_str("}\n");
+        Ok(line)
+    }
+
+    fn write(&self, line: &str) -> io::Result<()> {
+        use std::io::Write;
+
+        let mut io = self.io.lock().map_err(|_| Error::other("locking error"))?;
+        io.write_all(line.as_bytes())?;
+        io.flush()
+    }
+}
+
+/// Append `s` to `io` as a quoted JSON string
+fn write_json_string(io: &mut String, s: &str) {
+    use std::fmt::Write;
+
+    io.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => io.push_str("\\\""),
+            '\\' => io.push_str("\\\\"),
+            '\n' => io.push_str("\\n"),
+            '\r' => io.push_str("\\r"),
+            '\t' => io.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(io, "\\u{:04x}", c as u32);
+            }
+            c => io.push(c),
+        }
+    }
+    io.push('"');
+}
+
+/// Serializer writing `,"key":"value"` JSON members
+struct JsonSerializer<'a> {
+    io: &'a mut String,
+}
+
+impl<'a> slog::Serializer for JsonSerializer<'a> {
+    fn emit_arguments(&mut self, key: &str, val: &fmt::Arguments) -> slog::Result {
+        self.io.push(',');
+        write_json_string(self.io, key);
+        self.io.push(':');
+        write_json_string(self.io, &val.to_string());
+        Ok(())
+    }
+}
+
+/// The most recent error returned by `Streamer3164::log`
+#[derive(Clone, Debug)]
+pub struct LastError {
+    /// Kind of the error
+    pub kind: io::ErrorKind,
+    /// Text of the error
+    pub message: String,
+    /// When the error happened
+    pub time: std::time::SystemTime,
 }
 
 #[cfg(debug_assertions)]
+#[allow(unexpected_cfgs)]
 fn get_default_level() -> Level {
     if cfg!(feature = "max_level_trace") {
         Level::Trace
@@ -102,12 +540,16 @@ fn get_default_level() -> Level {
         Level::Warning
     } else if cfg!(feature = "max_level_error") {
         Level::Error
-    } else { // max_level_off
+    } else if cfg!(feature = "max_level_off") {
         Level::Critical
+    } else {
+        // Let slog's own compile-time filtering decide
+        Level::Trace
     }
 }
 
 #[cfg(not(debug_assertions))]
+#[allow(unexpected_cfgs)]
 fn get_default_level() -> Level {
     if cfg!(feature = "release_max_level_trace") {
         Level::Trace
@@ -119,26 +561,221 @@ fn get_default_level() -> Level {
         Level::Warning
     } else if cfg!(feature = "release_max_level_error") {
         Level::Error
-    } else { // release_max_level_off
+    } else if cfg!(feature = "release_max_level_off") {
         Level::Critical
+    } else {
+        // Let slog's own compile-time filtering decide
+        Level::Trace
     }
 }
 
 impl Streamer3164 {
     /// Create new syslog ``Streamer` using given `format` and logging level.
+    ///
+    /// Unlike the other constructors, this can't warn about `LOG_KERN`:
+    /// the facility is private to `logger`.
     pub fn new_with_level(logger: Box<SysLogger>, level: Level) -> Self {
         Streamer3164 {
             io: Mutex::new(logger),
             format: Format3164::new(),
-            level,
+            level: LevelHandle::new(level),
+            sampling: None,
+            buffer_capacity: 0,
+            console_fallback: None,
+            console: PathBuf::from(CONSOLE),
+            catch_panics: false,
+            last_error: Mutex::new(None),
+            sidecar: None,
+            reconnect: None,
+            framer: None,
+            dedup: None,
         }
     }
 
     /// Create new syslog ``Streamer` using given `format` and the default logging level.
+    ///
+    /// Doesn't warn about `LOG_KERN` either; see `new_with_level`.
     pub fn new(logger: Box<SysLogger>) -> Self {
         let level = get_default_level();
         Self::new_with_level(logger, level)
     }
+
+    /// Format records with `format`
+    pub fn with_format(self, format: Format3164) -> Self {
+        let mut s = self;
+        s.format = format;
+        s
+    }
+
+    /// Handle to change the logging level while the drain is in use
+    pub fn level_handle(&self) -> LevelHandle {
+        self.level.clone()
+    }
+
+    /// The last error `log` returned, if any
+    ///
+    /// Useful to detect that logging is degraded when the errors
+    /// themselves are discarded, e.g. by `Drain::ignore_res`. Keep the
+    /// drain in an `Arc` to query it after handing it to a `Logger`.
+    pub fn last_error(&self) -> Option<LastError> {
+        match self.last_error.lock() {
+            Ok(last_error) => last_error.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    fn record_error(&self, e: &io::Error) {
+        let last_error = LastError {
+            kind: e.kind(),
+            message: e.to_string(),
+            time: std::time::SystemTime::now(),
+        };
+        match self.last_error.lock() {
+            Ok(mut slot) => *slot = Some(last_error),
+            Err(poisoned) => *poisoned.into_inner() = Some(last_error),
+        }
+    }
+
+    /// Whether a record at `level` passes the level and sampling checks
+    fn enabled(&self, level: Level) -> bool {
+        if !level.is_at_least(self.level.get()) {
+            return false;
+        }
+        match self.sampling {
+            Some(ref sampling) => sampling.keep(level),
+            None => true,
+        }
+    }
+
+    /// Send the formatted message `buf`, unless it repeats the previous
+    /// one and `dedup` is set
+    fn write(&self, level: Level, buf: &str) -> io::Result<()> {
+        let dedup = match self.dedup {
+            Some(ref dedup) => dedup,
+            None => return self.write_now(level, buf),
+        };
+        let mut last = dedup.last.lock().map_err(|_| Error::other("locking error"))?;
+        let now = Instant::now();
+        let expired = now.duration_since(last.since) >= dedup.timeout;
+        if last.level == Some(level) && last.message == buf && !expired {
+            last.count += 1;
+            return Ok(());
+        }
+        if let (Some(last_level), true) = (last.level, last.count > 0) {
+            let count = std::mem::replace(&mut last.count, 0);
+            self.write_now(last_level, &format!("last message repeated {} times", count))?;
+        }
+        last.level = Some(level);
+        last.message.clear();
+        last.message.push_str(buf);
+        last.since = now;
+        self.write_now(level, buf)
+    }
+
+    /// When the pending count of repeats is due, if there is one
+    fn repeated_deadline(&self) -> Option<Instant> {
+        let dedup = self.dedup.as_ref()?;
+        let last = dedup.last.lock().ok()?;
+        if last.count > 0 {
+            Some(last.since + dedup.timeout)
+        } else {
+            None
+        }
+    }
+
+    /// Send the pending count of repeats if `dedup`'s timeout has passed
+    /// since the repeated message was sent
+    fn flush_repeated(&self) -> io::Result<()> {
+        let dedup = match self.dedup {
+            Some(ref dedup) => dedup,
+            None => return Ok(()),
+        };
+        let mut last = dedup.last.lock().map_err(|_| Error::other("locking error"))?;
+        let expired = Instant::now().duration_since(last.since) >= dedup.timeout;
+        if let (Some(level), true) = (last.level, last.count > 0 && expired) {
+            let count = std::mem::replace(&mut last.count, 0);
+            self.write_now(level, &format!("last message repeated {} times", count))?;
+        }
+        Ok(())
+    }
+
+    /// Send the formatted message `buf`, reconnecting if configured to
+    fn write_now(&self, level: Level, buf: &str) -> io::Result<()> {
+        let mut io = 
+            self.io
+            .lock()
+            .map_err(|_| Error::other("locking error"))?;
+
+        let mut res = self.send(level, &mut io, buf);
+        if res.is_err() {
+            if let Some(ref reconnect) = self.reconnect {
+                if let Ok(stream) = reconnect.run(&mut io) {
+                    if let Some(ref framer) = self.framer {
+                        framer.replace_stream(FramedStream::Tcp(stream));
+                    }
+                    res = self.send(level, &mut io, buf);
+                }
+            }
+        }
+        res
+    }
+
+    /// Record `e`, and write `buf` to the console if configured to
+    fn failed(&self, level: Level, buf: &str, e: &io::Error) {
+        self.record_error(e);
+        if let Some(console_level) = self.console_fallback {
+            if level.is_at_least(console_level) {
+                write_console(&self.console, buf);
+            }
+        }
+    }
+
+    fn send(&self, level: Level, io: &mut SysLogger, buf: &str) -> io::Result<()> {
+        match self.framer {
+            Some(ref framer) => framer.send(level, buf),
+            None => log_with_level(level, io, buf),
+        }
+    }
+
+    /// Format `record` into `buf`, catching panics if configured to
+    ///
+    /// Returns the sidecar line, if there is a sidecar and formatting
+    /// didn't panic.
+    fn format_record(&self, buf: &mut String, record: &Record, values: &OwnedKVList) -> slog::Result<Option<String>> {
+        let format = |buf: &mut String| {
+            self.format.format(buf, record, values)?;
+            self.sidecar.as_ref().map(|sidecar| sidecar.line(record, values)).transpose()
+        };
+        if !self.catch_panics {
+            return format(buf);
+        }
+        match panic::catch_unwind(AssertUnwindSafe(|| format(&mut *buf))) {
+            Ok(res) => res,
+            Err(_) => {
+                buf.clear();
+                buf.push_str("slog-syslog: panicked while formatting a log record");
+                Ok(None)
+            }
+        }
+    }
+}
+
+impl Drop for Streamer3164 {
+    fn drop(&mut self) {
+        let pending = match self.dedup {
+            Some(ref mut dedup) => {
+                let last = match dedup.last.get_mut() {
+                    Ok(last) => last,
+                    Err(poisoned) => poisoned.into_inner(),
+                };
+                last.level.filter(|_| last.count > 0).map(|level| (level, last.count))
+            }
+            None => None,
+        };
+        if let Some((level, count)) = pending {
+            let _ = self.write_now(level, &format!("last message repeated {} times", count));
+        }
+    }
 }
 
 impl Drain for Streamer3164 {
@@ -146,101 +783,761 @@ impl Drain for Streamer3164 {
     type Ok = ();
 
     fn log(&self, info: &Record, logger_values: &OwnedKVList) -> io::Result<()> {
-        if self.level > info.level() {
+        if !self.enabled(info.level()) {
             return Ok(())
         }
         TL_BUF.with(|buf| {
             let mut buf = buf.borrow_mut();
+            buf.reserve(self.buffer_capacity);
             let res = {
                 || {
-                    self.format.format(&mut *buf, info, logger_values)?;
-                    let io = 
-                        self.io
-                        .lock()
-                        .map_err(|_| Error::new(ErrorKind::Other, "locking error"))?;
-
-                    let buf = String::from_utf8_lossy(&buf);
-
-                    log_with_level(info.level(), io, &buf)
+                    let line = self.format_record(&mut buf, info, logger_values)?;
+                    self.write(info.level(), &buf)?;
+                    Ok(line)
                 }
             }();
+            if let Err(ref e) = res {
+                self.failed(info.level(), &buf, e);
+            }
             buf.clear();
-            res
+
+            // Syslog has the message, so this is not a delivery failure
+            match (res?, &self.sidecar) {
+                (Some(line), Some(sidecar)) => sidecar.write(&line),
+                _ => Ok(()),
+            }
         })
     }
 }
 
-/// Formatter to format defined in RFC 3164
-pub struct Format3164;
+/// What `AsyncStreamer3164` does with a record when its queue is full
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Wait for the worker thread to make room
+    Block,
+    /// Discard the record, counting it in `AsyncStreamer3164::dropped`
+    Drop,
+}
 
-impl Format3164 {
-    /// Create new `Format3164`
-    pub fn new() -> Self {
-        Format3164
+/// A formatted record, and its sidecar line if there is a sidecar
+type Queued = (Level, String, Option<String>);
+
+/// Drain formatting records on the logging thread and writing them to
+/// syslog from a worker thread
+///
+/// Wraps a `Streamer3164`, so that a slow syslog server doesn't stall
+/// the threads that log. Records are formatted right away and put on a
+/// bounded queue; a dedicated thread takes them off and writes them.
+///
+/// Write errors happen on the worker thread, so `log` can't return
+/// them. They still show up in `last_error` and go to the console
+/// fallback, if any. The structured sidecar, if any, is written by the
+/// worker thread once a record was sent; its errors are ignored.
+///
+/// Dropping the drain waits for the queued records to be written.
+pub struct AsyncStreamer3164 {
+    inner: Arc<Streamer3164>,
+    queue: mpsc::SyncSender<Option<Queued>>,
+    overflow: OverflowPolicy,
+    dropped: AtomicU64,
+    worker: Mutex<Option<thread::JoinHandle<()>>>,
+}
+
+impl AsyncStreamer3164 {
+    /// Start a worker thread for `streamer`, queueing up to `capacity`
+    /// records
+    pub fn new(streamer: Streamer3164, capacity: usize, overflow: OverflowPolicy) -> io::Result<Self> {
+        let inner = Arc::new(streamer);
+        let (queue, rx) = mpsc::sync_channel::<Option<Queued>>(capacity);
+        let worker = {
+            let inner = inner.clone();
+            thread::Builder::new()
+                .name("slog-syslog".to_string())
+                .spawn(move || loop {
+                    // Wake up for a pending dedup count even if no
+                    // record comes
+                    let item = match inner.repeated_deadline() {
+                        Some(deadline) => match rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                            Ok(item) => item,
+                            Err(mpsc::RecvTimeoutError::Timeout) => {
+                                if let Err(e) = inner.flush_repeated() {
+                                    inner.record_error(&e);
+                                }
+                                continue;
+                            }
+                            Err(mpsc::RecvTimeoutError::Disconnected) => None,
+                        },
+                        None => rx.recv().unwrap_or(None),
+                    };
+                    let (level, buf, line) = match item {
+                        Some(item) => item,
+                        None => break,
+                    };
+                    match inner.write(level, &buf) {
+                        Ok(()) => {
+                            if let (Some(line), Some(sidecar)) = (line, &inner.sidecar) {
+                                let _ = sidecar.write(&line);
+                            }
+                        }
+                        Err(e) => inner.failed(level, &buf, &e),
+                    }
+                })?
+        };
+        Ok(AsyncStreamer3164 {
+            inner,
+            queue,
+            overflow,
+            dropped: AtomicU64::new(0),
+            worker: Mutex::new(Some(worker)),
+        })
     }
 
-    fn format(
-        &self,
-        io: &mut dyn io::Write,
-        record: &Record,
-        logger_kv: &OwnedKVList,
-    ) -> io::Result<()> {
-        write!(io, "{}", record.msg())?;
+    /// Number of records discarded because the queue was full
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
 
-        let mut ser = KSV::new(io);
-        {
-            logger_kv.serialize(record, &mut ser)?;
-            record.kv().serialize(record, &mut ser)?;
+    /// Handle to change the logging level while the drain is in use
+    pub fn level_handle(&self) -> LevelHandle {
+        self.inner.level_handle()
+    }
+
+    /// The last error writing to syslog, if any
+    ///
+    /// See `Streamer3164::last_error`.
+    pub fn last_error(&self) -> Option<LastError> {
+        self.inner.last_error()
+    }
+}
+
+impl Drain for AsyncStreamer3164 {
+    type Err = io::Error;
+    type Ok = ();
+
+    fn log(&self, info: &Record, logger_values: &OwnedKVList) -> io::Result<()> {
+        if !self.inner.enabled(info.level()) {
+            return Ok(())
+        }
+        let mut buf = String::with_capacity(self.inner.buffer_capacity.max(128));
+        let line = self.inner.format_record(&mut buf, info, logger_values)?;
+        let item = Some((info.level(), buf, line));
+        let stopped = || Error::other("syslog worker thread has stopped");
+        match self.overflow {
+            OverflowPolicy::Block => self.queue.send(item).map_err(|_| stopped())?,
+            OverflowPolicy::Drop => match self.queue.try_send(item) {
+                Ok(()) => {}
+                Err(mpsc::TrySendError::Full(_)) => {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                    return Ok(());
+                }
+                Err(mpsc::TrySendError::Disconnected(_)) => return Err(stopped()),
+            },
         }
         Ok(())
     }
 }
 
-/// Key-Separator-Value serializer
-struct KSV<W: io::Write> {
-    io: W,
+impl Drop for AsyncStreamer3164 {
+    fn drop(&mut self) {
+        let _ = self.queue.send(None);
+        let worker = match self.worker.get_mut() {
+            Ok(worker) => worker.take(),
+            Err(poisoned) => poisoned.into_inner().take(),
+        };
+        if let Some(worker) = worker {
+            let _ = worker.join();
+        }
+    }
 }
 
-impl<W: io::Write> KSV<W> {
-    fn new(io: W) -> Self {
-        KSV { io: io }
+/// Appended by default to a message when one of its `Limits` was hit
+const TRUNCATION_MARKER: &str = "…trunc";
+
+/// Shorten `s` to at most `max` bytes, without splitting a character
+fn truncate_on_char_boundary(s: &mut String, max: usize) {
+    if s.len() <= max {
+        return;
+    }
+    let mut end = max;
+    while !s.is_char_boundary(end) {
+        end -= 1;
     }
+    s.truncate(end);
 }
 
-impl<W: io::Write> slog::Serializer for KSV<W> {
-    fn emit_arguments(&mut self, key: &str, val: &fmt::Arguments) -> slog::Result {
-        write!(self.io, ", {}: {}", key, val)?;
-        Ok(())
-    }
+/// Size limits applied while formatting a record
+///
+/// Every limit is optional. A cut value ends with a marker, `…trunc`
+/// unless changed with `Format3164::with_truncation_marker`; so does the
+/// whole message when pairs are dropped or it is clamped to `max_bytes`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Limits {
+    /// Maximum length of the whole message in bytes, marker included
+    ///
+    /// This covers the message and key-values, not the syslog header
+    /// added in front of them.
+    pub max_bytes: Option<usize>,
+    /// Maximum number of key-value pairs; further pairs are dropped
+    pub max_keys: Option<usize>,
+    /// Maximum length of a single value in bytes, marker excluded
+    pub max_value_len: Option<usize>,
 }
 
-enum SyslogKind {
-    Unix {
-        path: PathBuf,
-    },
-    Tcp {
-        server: SocketAddr,
-        hostname: String,
-    },
+/// Formatter to format defined in RFC 3164
+#[derive(Default)]
+pub struct Format3164 {
+    limits: Limits,
+    value_limits: HashMap<String, usize>,
+    kv_min_level: Option<Level>,
+    escape_control_chars: bool,
+    static_fields: Vec<(&'static str, String)>,
+    truncation_marker: Option<String>,
+    location: bool,
+    separators: Option<(String, String)>,
+}
+
+impl Format3164 {
+    /// Create new `Format3164`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Apply `limits` to every formatted message
+    ///
+    /// Values are cut first, then pairs beyond `max_keys` are dropped,
+    /// and finally the whole message is clamped to `max_bytes`. All
+    /// cuts fall on UTF-8 character boundaries.
+    pub fn with_limits(self, limits: Limits) -> Self {
+        let mut s = self;
+        s.limits = limits;
+        s
+    }
+
+    /// Limit whole messages to `max` bytes
+    ///
+    /// Shorthand for setting `Limits::max_bytes`. Many syslog daemons
+    /// drop or split messages over 1024 bytes; as the syslog header
+    /// takes some of those, pick `max` with some room to spare.
+    pub fn with_max_len(self, max: usize) -> Self {
+        let mut s = self;
+        s.limits.max_bytes = Some(max);
+        s
+    }
+
+    /// Text appended to values and messages cut by a limit
+    ///
+    /// Defaults to `…trunc`. Room for the marker is left within
+    /// `max_bytes`; a marker longer than `max_bytes` is cut itself.
+    pub fn with_truncation_marker<M: Into<String>>(self, marker: M) -> Self {
+        let mut s = self;
+        s.truncation_marker = Some(marker.into());
+        s
+    }
+
+    /// Limit values of `key` to `max` bytes
+    ///
+    /// Overrides `Limits::max_value_len` for this key, in either
+    /// direction: e.g. allow a `stacktrace` of 4 KiB while every other
+    /// value is capped at 256 bytes.
+    pub fn with_value_limit<K: Into<String>>(self, key: K, max: usize) -> Self {
+        let mut s = self;
+        s.value_limits.insert(key.into(), max);
+        s
+    }
+
+    /// Append `key: value` to every message
+    ///
+    /// Static fields come after the logger and record key-values and go
+    /// through the same limits and escaping. Unlike other key-values,
+    /// they are kept on records below `with_kv_min_level`. Can be called
+    /// several times to add several fields.
+    pub fn with_static_field<V: Into<String>>(self, key: &'static str, value: V) -> Self {
+        let mut s = self;
+        s.static_fields.push((key, value.into()));
+        s
+    }
+
+    /// Escape control characters in the message and values
+    ///
+    /// Every control character except tab is written as `\xNN`. This
+    /// includes CR/LF and the ESC that starts ANSI terminal sequences.
+    /// It stops log lines from being forged with embedded newlines, and
+    /// stops escape sequences from acting on a terminal that later
+    /// displays the logs. Keys are written as-is.
+    pub fn with_escaped_control_chars(self) -> Self {
+        let mut s = self;
+        s.escape_control_chars = true;
+        s
+    }
+
+    /// Only append key-values to records at or above `level`
+    ///
+    /// Less severe records are sent as the bare message, which keeps the
+    /// volume of info/debug output down while warnings and errors keep
+    /// their full context.
+    pub fn with_kv_min_level(self, level: Level) -> Self {
+        let mut s = self;
+        s.kv_min_level = Some(level);
+        s
+    }
+
+    /// Write key-values as `{pair_sep}{key}{kv_sep}{value}`
+    ///
+    /// The default of `": "` and `", "` gives `msg, k: v, k2: v2`;
+    /// `"="` and `" "` give `msg k=v k2=v2`.
+    pub fn with_separators<K: Into<String>, P: Into<String>>(self, kv_sep: K, pair_sep: P) -> Self {
+        let mut s = self;
+        s.separators = Some((kv_sep.into(), pair_sep.into()));
+        s
+    }
+
+    /// Append the source location of each record
+    ///
+    /// Messages end with ` [src=module:file:line]`, after the
+    /// key-values. Empty parts are left out, and so is the whole suffix
+    /// when the record carries no location at all.
+    pub fn with_location(self) -> Self {
+        let mut s = self;
+        s.location = true;
+        s
+    }
+
+    fn truncation_marker(&self) -> &str {
+        self.truncation_marker.as_deref().unwrap_or(TRUNCATION_MARKER)
+    }
+
+    /// Format `record` into `io`
+    ///
+    /// Everything is written through `fmt::Write`, so the output is
+    /// always valid UTF-8 and can be handed to syslog without any
+    /// conversion.
+    fn format(
+        &self,
+        io: &mut String,
+        record: &Record,
+        logger_kv: &OwnedKVList,
+    ) -> slog::Result {
+        use std::fmt::Write;
+
+        if self.escape_control_chars {
+            write!(ControlCharEscaper(&mut *io), "{}", record.msg())?;
+        } else {
+            write!(io, "{}", record.msg())?;
+        }
+
+        let mut ser = KeyValueSerializer::new(&mut *io, self);
+        let with_kv = match self.kv_min_level {
+            Some(level) => record.level().is_at_least(level),
+            None => true,
+        };
+        if with_kv {
+            logger_kv.serialize(record, &mut ser)?;
+            record.kv().serialize(record, &mut ser)?;
+        }
+        for (key, value) in &self.static_fields {
+            slog::Serializer::emit_str(&mut ser, key, value)?;
+        }
+        let mut truncated = ser.dropped;
+        if self.location {
+            write_location(io, record)?;
+        }
+        if let Some(max) = self.limits.max_bytes {
+            truncated |= io.len() > max;
+            if truncated {
+                truncate_on_char_boundary(io, max.saturating_sub(self.truncation_marker().len()));
+            }
+        }
+        if truncated {
+            io.push_str(self.truncation_marker());
+            if let Some(max) = self.limits.max_bytes {
+                // Only matters when the marker alone is longer than `max`
+                truncate_on_char_boundary(io, max);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Write ` [src=module:file:line]`, skipping empty parts
+fn write_location(io: &mut String, record: &Record) -> fmt::Result {
+    use std::fmt::Write;
+
+    let line = match record.line() {
+        0 => String::new(),
+        line => line.to_string(),
+    };
+    let parts = [record.module(), record.file(), line.as_str()];
+    let mut parts = parts.iter().filter(|p| !p.is_empty());
+    if let Some(first) = parts.next() {
+        write!(io, " [src={}", first)?;
+        for part in parts {
+            write!(io, ":{}", part)?;
+        }
+        io.push(']');
+    }
+    Ok(())
+}
+
+/// Key-Separator-Value serializer
+struct KeyValueSerializer<'a, W: fmt::Write> {
+    io: W,
+    format: &'a Format3164,
+    keys: usize,
+    dropped: bool,
+}
+
+impl<'a, W: fmt::Write> KeyValueSerializer<'a, W> {
+    fn new(io: W, format: &'a Format3164) -> Self {
+        KeyValueSerializer {
+            io,
+            format,
+            keys: 0,
+            dropped: false,
+        }
+    }
+}
+
+impl<'a, W: fmt::Write> slog::Serializer for KeyValueSerializer<'a, W> {
+    fn emit_arguments(&mut self, key: &str, val: &fmt::Arguments) -> slog::Result {
+        let limits = &self.format.limits;
+        if limits.max_keys.is_some_and(|max| self.keys >= max) {
+            self.dropped = true;
+            return Ok(());
+        }
+        self.keys += 1;
+        let max_value_len = match self.format.value_limits.get(key) {
+            Some(&max) => Some(max),
+            None => limits.max_value_len,
+        };
+        let (kv_sep, pair_sep) = match self.format.separators {
+            Some((ref kv_sep, ref pair_sep)) => (kv_sep.as_str(), pair_sep.as_str()),
+            None => (": ", ", "),
+        };
+        write!(self.io, "{}{}{}", pair_sep, key, kv_sep)?;
+        match max_value_len {
+            Some(max) => {
+                let mut val = val.to_string();
+                let cut = val.len() > max;
+                truncate_on_char_boundary(&mut val, max);
+                self.write_value(format_args!("{}", val))?;
+                if cut {
+                    self.io.write_str(self.format.truncation_marker())?;
+                }
+            }
+            None => self.write_value(*val)?,
+        }
+        Ok(())
+    }
+}
+
+impl<'a, W: fmt::Write> KeyValueSerializer<'a, W> {
+    fn write_value(&mut self, val: fmt::Arguments) -> fmt::Result {
+        use std::fmt::Write;
+
+        if self.format.escape_control_chars {
+            write!(ControlCharEscaper(&mut self.io), "{}", val)
+        } else {
+            write!(self.io, "{}", val)
+        }
+    }
+}
+
+/// Writer escaping control characters, except tab, as `\xNN`
+struct ControlCharEscaper<W: fmt::Write>(W);
+
+impl<W: fmt::Write> fmt::Write for ControlCharEscaper<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut rest = s;
+        while let Some(pos) = rest.find(|c: char| c.is_control() && c != '\t') {
+            self.0.write_str(&rest[..pos])?;
+            let c = rest[pos..].chars().next().unwrap();
+            write!(self.0, "\\x{:02x}", c as u32)?;
+            rest = &rest[pos + c.len_utf8()..];
+        }
+        self.0.write_str(rest)
+    }
+}
+
+/// Socket options for TCP transports
+#[derive(Clone, Default)]
+struct TcpOptions {
+    keepalive: Option<Duration>,
+    write_timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+    reconnect_attempts: u32,
+    reconnect_backoff: Duration,
+    framing: Option<TcpFraming>,
+}
+
+/// Socket option setting the keepalive idle time, where libc has one
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "dragonfly",
+    target_os = "netbsd",
+    target_os = "illumos",
+    target_os = "solaris",
+))]
+const TCP_KEEPALIVE_IDLE: Option<libc::c_int> = Some(libc::TCP_KEEPIDLE);
+#[cfg(target_vendor = "apple")]
+const TCP_KEEPALIVE_IDLE: Option<libc::c_int> = Some(libc::TCP_KEEPALIVE);
+#[cfg(all(
+    unix,
+    not(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "freebsd",
+        target_os = "dragonfly",
+        target_os = "netbsd",
+        target_os = "illumos",
+        target_os = "solaris",
+        target_vendor = "apple",
+    ))
+))]
+const TCP_KEEPALIVE_IDLE: Option<libc::c_int> = None;
+
+/// Enable TCP keepalive on `stream`, probing after `idle` of silence
+///
+/// Where there is no socket option for the idle time, e.g. on OpenBSD,
+/// `idle` is ignored and the system-wide setting applies.
+#[cfg(unix)]
+fn set_tcp_keepalive(stream: &TcpStream, idle: Duration) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    fn setsockopt(fd: libc::c_int, level: libc::c_int, name: libc::c_int, value: libc::c_int) -> io::Result<()> {
+        let ret = unsafe {
+            libc::setsockopt(
+                fd,
+                level,
+                name,
+                &value as *const libc::c_int as *const libc::c_void,
+                std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+        if ret == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    let fd = stream.as_raw_fd();
+    setsockopt(fd, libc::SOL_SOCKET, libc::SO_KEEPALIVE, 1)?;
+    if let Some(name) = TCP_KEEPALIVE_IDLE {
+        let secs = idle.as_secs().clamp(1, libc::c_int::MAX as u64) as libc::c_int;
+        setsockopt(fd, libc::IPPROTO_TCP, name, secs)?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_tcp_keepalive(_stream: &TcpStream, _idle: Duration) -> io::Result<()> {
+    Err(Error::new(io::ErrorKind::Unsupported, "TCP keepalive is not supported on this platform"))
+}
+
+/// Create a TCP logger connected to `server`, and a second handle to
+/// its socket.
+///
+/// The buffer is disabled so that every message is sent right away;
+/// the `syslog` crate never flushes it.
+fn tcp_connect(format: syslog::Formatter3164, server: SocketAddr, options: &TcpOptions) -> io::Result<(SysLogger, TcpStream)> {
+    let stream = match options.connect_timeout {
+        Some(timeout) => TcpStream::connect_timeout(&server, timeout)?,
+        None => TcpStream::connect(server)?,
+    };
+    stream.set_write_timeout(options.write_timeout)?;
+    if let Some(idle) = options.keepalive {
+        set_tcp_keepalive(&stream, idle)?;
+    }
+    let handle = stream.try_clone()?;
+    let backend = syslog::LoggerBackend::Tcp(io::BufWriter::with_capacity(0, stream));
+    Ok((syslog::Logger::new(backend, format), handle))
+}
+
+/// Create a logger writing to a `SOCK_STREAM` unix socket at `path`,
+/// and the framer that sends its messages.
+#[cfg(unix)]
+fn unix_stream(format: syslog::Formatter3164, path: &Path, framing: TcpFraming) -> io::Result<(SysLogger, Framer)> {
+    let socket = std::os::unix::net::UnixStream::connect(path)?;
+    let handle = socket.try_clone()?;
+    let backend = syslog::LoggerBackend::UnixStream(io::BufWriter::with_capacity(0, socket));
+    let framer = Framer::new(framing, format.clone(), FramedStream::Unix(handle));
+    Ok((syslog::Logger::new(backend, format), framer))
+}
+
+#[cfg(not(unix))]
+fn unix_stream(_format: syslog::Formatter3164, _path: &Path, _framing: TcpFraming) -> io::Result<(SysLogger, Framer)> {
+    Err(Error::new(io::ErrorKind::Unsupported, "unix sockets are not supported on this platform"))
+}
+
+/// Create a logger writing to the unix socket at `path`.
+///
+/// Like `syslog::unix_custom`, this connects with a datagram socket and
+/// falls back to a stream socket if `path` is one. Unlike it, messages on
+/// a stream socket are framed with `framing` and sent right away.
+#[cfg(unix)]
+fn unix_connect(format: syslog::Formatter3164, path: &Path, framing: TcpFraming) -> io::Result<(SysLogger, Option<Framer>)> {
+    let socket = std::os::unix::net::UnixDatagram::unbound()?;
+    match socket.connect(path) {
+        Ok(()) => Ok((syslog::Logger::new(syslog::LoggerBackend::Unix(socket), format), None)),
+        Err(ref e) if e.raw_os_error() == Some(libc::EPROTOTYPE) => {
+            let (log, framer) = unix_stream(format, path, framing)?;
+            Ok((log, Some(framer)))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(not(unix))]
+fn unix_connect(_format: syslog::Formatter3164, _path: &Path, _framing: TcpFraming) -> io::Result<(SysLogger, Option<Framer>)> {
+    Err(Error::new(io::ErrorKind::Unsupported, "unix sockets are not supported on this platform"))
+}
+
+/// Paths probed by `TransportSpec::UnixAuto`, in order
+const UNIX_SOCKET_CANDIDATES: &[&str] = &["/dev/log", "/var/run/syslog", "/var/run/log"];
+
+/// Describe why `path` can't be used as a syslog socket, if it can't
+#[cfg(unix)]
+fn unix_socket_problem(path: &Path) -> Option<&'static str> {
+    use std::os::unix::fs::FileTypeExt;
+
+    match std::fs::metadata(path) {
+        Err(_) => Some("missing"),
+        Ok(ref m) if m.is_dir() => Some("directory"),
+        Ok(ref m) if !m.file_type().is_socket() => Some("not a socket"),
+        Ok(_) => None,
+    }
+}
+
+#[cfg(not(unix))]
+fn unix_socket_problem(_path: &Path) -> Option<&'static str> {
+    Some("unsupported platform")
+}
+
+/// Connect to the first of `UNIX_SOCKET_CANDIDATES` that is a socket
+fn unix_auto(facility: syslog::Facility, framing: TcpFraming) -> io::Result<(SysLogger, Option<Framer>)> {
+    let candidates: Vec<&Path> = UNIX_SOCKET_CANDIDATES.iter().map(Path::new).collect();
+    unix_auto_from(facility, &candidates, framing)
+}
+
+/// Connect to the first of `candidates` that is a socket
+fn unix_auto_from(
+    facility: syslog::Facility,
+    candidates: &[&Path],
+    framing: TcpFraming,
+) -> io::Result<(SysLogger, Option<Framer>)> {
+    let mut problems = Vec::new();
+    for path in candidates {
+        if let Some(problem) = unix_socket_problem(path) {
+            problems.push(format!("{}: {}", path.display(), problem));
+            continue;
+        }
+        let format = syslog_format3164(facility, None);
+        match unix_connect(format, path, framing) {
+            Ok(connected) => return Ok(connected),
+            Err(e) => problems.push(format!("{}: connect failed: {}", path.display(), e)),
+        }
+    }
+    Err(Error::new(
+        io::ErrorKind::NotFound,
+        format!("no local syslog socket found: {}", problems.join("; ")),
+    ))
+}
+
+/// Transport used to reach syslog
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub enum TransportSpec {
+    /// Local syslogging over a unix socket
+    Unix {
+        /// Path of the unix socket
+        path: PathBuf,
+    },
+    /// Local syslogging over a `SOCK_STREAM` unix socket
+    ///
+    /// Messages are framed as set with `SyslogBuilder::tcp_framing`, by
+    /// default with a LF after each.
+    UnixStream {
+        /// Path of the unix socket
+        path: PathBuf,
+    },
+    /// Local syslogging over the first socket found among `/dev/log`,
+    /// `/var/run/syslog` and `/var/run/log`
+    ///
+    /// Paths that are missing, directories (like `/var/run/log` under
+    /// systemd-journald) or not sockets are skipped without connecting.
+    UnixAuto,
+    /// Remote TCP syslogging
+    Tcp {
+        /// Address of the syslog server
+        server: SocketAddr,
+        /// Hostname to put in the messages
+        hostname: String,
+    },
+    /// Remote UDP syslogging
     Udp {
+        /// Local address to bind to
         local: SocketAddr,
+        /// Address of the syslog server
         host: SocketAddr,
+        /// Hostname to put in the messages
         hostname: String,
     },
 }
 
+impl fmt::Display for TransportSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TransportSpec::Unix { ref path } => write!(f, "unix {}", path.display()),
+            TransportSpec::UnixStream { ref path } => write!(f, "unix stream {}", path.display()),
+            TransportSpec::UnixAuto => write!(f, "unix (auto)"),
+            TransportSpec::Tcp { ref server, .. } => write!(f, "tcp {}", server),
+            TransportSpec::Udp { ref host, .. } => write!(f, "udp {}", host),
+        }
+    }
+}
+
 /// Builder pattern for constructing a syslog
+///
+/// The `tcp_*` options only have an effect together with `tcp`, and
+/// `udp_connected` only with `udp`; none of them is set by default. A
+/// `Streamer3164` writes under a mutex, so a write that blocks, such as
+/// one to a slow collector or while reconnecting, also blocks every other
+/// thread logging to it. `start_async` avoids that.
 pub struct SyslogBuilder {
     facility: Option<syslog::Facility>,
     level: Level,
-    logkind: Option<SyslogKind>,
+    transports: Vec<TransportSpec>,
+    udp_connected: bool,
+    sample: Option<(Level, u32)>,
+    kern_warning: bool,
+    buffer_capacity: usize,
+    console_fallback: Option<Level>,
+    catch_panics: bool,
+    format: Format3164,
+    prefer_authpriv: bool,
+    tcp: TcpOptions,
+    sidecar: Option<Box<dyn io::Write + Send>>,
+    dedup: Option<Duration>,
 }
 impl Default for SyslogBuilder {
     fn default() -> Self {
         Self {
             facility: None,
             level: Level::Trace,
-            logkind: None,
+            transports: Vec::new(),
+            udp_connected: false,
+            sample: None,
+            kern_warning: true,
+            buffer_capacity: 0,
+            console_fallback: None,
+            catch_panics: false,
+            format: Format3164::new(),
+            prefer_authpriv: false,
+            tcp: TcpOptions::default(),
+            sidecar: None,
+            dedup: None,
         }
     }
 }
@@ -259,6 +1556,13 @@ impl SyslogBuilder {
         s
     }
 
+    /// Send `LOG_AUTH` messages to `LOG_AUTHPRIV` instead
+    pub fn prefer_authpriv(self) -> Self {
+        let mut s = self;
+        s.prefer_authpriv = true;
+        s
+    }
+
     /// Filter Syslog by level
     pub fn level(self, lvl: slog::Level) -> Self {
         let mut s = self;
@@ -266,90 +1570,1460 @@ impl SyslogBuilder {
         s
     }
 
-    /// Remote UDP syslogging
-    pub fn udp<S: AsRef<str>>(self, local: SocketAddr, host: SocketAddr, hostname: S) -> Self {
+    /// Also write every record sent to syslog as a JSON line to `io`
+    ///
+    /// Errors writing the line are returned by `log` but are not delivery
+    /// failures; with `start_async` they are ignored.
+    pub fn structured_sidecar<W: io::Write + Send + 'static>(self, io: W) -> Self {
         let mut s = self;
-        let hostname = hostname.as_ref().to_string();
-        s.logkind = Some(SyslogKind::Udp {
-            local,
-            host,
-            hostname,
-        });
+        s.sidecar = Some(Box::new(io));
         s
     }
 
-    /// Remote TCP syslogging
-    pub fn tcp<S: AsRef<str>>(self, server: SocketAddr, hostname: S) -> Self {
+    /// Format records with `format`
+    ///
+    /// Replaces the format set up by earlier calls such as `limits`.
+    pub fn format(self, format: Format3164) -> Self {
         let mut s = self;
-        let hostname = hostname.as_ref().to_string();
-        s.logkind = Some(SyslogKind::Tcp { server, hostname });
+        s.format = format;
         s
     }
 
-    /// Local syslogging over a unix socket
-    pub fn unix<P: AsRef<Path>>(self, path: P) -> Self {
+    /// Limit the size of formatted messages
+    ///
+    /// See `Format3164::with_limits`.
+    pub fn limits(self, limits: Limits) -> Self {
         let mut s = self;
-        let path = path.as_ref().to_path_buf();
-        s.logkind = Some(SyslogKind::Unix { path });
+        s.format = s.format.with_limits(limits);
         s
     }
 
-    /// Start running
-    pub fn start(self) -> io::Result<Streamer3164> {
-        let facility = match self.facility {
-            Option::Some(x) => x,
-            Option::None => {
-                return Err(Error::new(
-                    ErrorKind::Other,
-                    "facility must be provided to the builder",
-                ));
-            }
-        };
-        let logkind = match self.logkind {
-            Option::Some(l) => l,
-            Option::None => {
-                return Err(Error::new(
-                    ErrorKind::Other,
-                    "no logger kind provided, library does not know what do initialize",
-                ));
-            }
-        };
-        let log = match logkind {
-            SyslogKind::Unix { path } => {
-                let format = syslog_format3164(facility, None);
-                syslog::unix_custom(format, path).map_err(handle_syslog_error)?
-            }
-            SyslogKind::Udp {
-                local,
-                host,
-                hostname,
-            } => {
-                let format = syslog_format3164(facility, Some(hostname));
-                syslog::udp(format, local, host).map_err(handle_syslog_error)?
-            },
-            SyslogKind::Tcp { server, hostname } => {
-                let format = syslog_format3164(facility, Some(hostname));
-                syslog::tcp(format, server).map_err(handle_syslog_error)?
-            },
-        };
-        Ok(Streamer3164::new_with_level(Box::new(log), self.level))
+    /// Append `key: value` to every message
+    ///
+    /// See `Format3164::with_static_field`.
+    pub fn static_field<V: Into<String>>(self, key: &'static str, value: V) -> Self {
+        let mut s = self;
+        s.format = s.format.with_static_field(key, value);
+        s
     }
-}
 
-/// `Streamer` to Unix syslog using RFC 3164 format
-pub fn unix_3164_with_level(facility: syslog::Facility, level: Level) -> io::Result<Streamer3164> {
-    let format = syslog_format3164(facility, None);
-    syslog::unix(format)
-        .map(Box::new)
-        .map(|logger| Streamer3164::new_with_level(logger, level))
-        .map_err(handle_syslog_error)
-}
+    /// Escape control characters in the message and values
+    ///
+    /// See `Format3164::with_escaped_control_chars`.
+    pub fn escape_control_chars(self) -> Self {
+        let mut s = self;
+        s.format = s.format.with_escaped_control_chars();
+        s
+    }
 
-/// `Streamer` to Unix syslog using RFC 3164 format
-pub fn unix_3164(facility: syslog::Facility) -> io::Result<Streamer3164> {
-    let format = syslog_format3164(facility, None);
-    syslog::unix(format)
-        .map(Box::new)
-        .map(Streamer3164::new)
-        .map_err(handle_syslog_error)
+    /// Only append key-values to records at or above `level`
+    ///
+    /// See `Format3164::with_kv_min_level`.
+    pub fn kv_min_level(self, level: slog::Level) -> Self {
+        let mut s = self;
+        s.format = s.format.with_kv_min_level(level);
+        s
+    }
+
+    /// Set the separators written around key-values
+    ///
+    /// See `Format3164::with_separators`.
+    pub fn separators<K: Into<String>, P: Into<String>>(self, kv_sep: K, pair_sep: P) -> Self {
+        let mut s = self;
+        s.format = s.format.with_separators(kv_sep, pair_sep);
+        s
+    }
+
+    /// Append the source location to every message
+    ///
+    /// See `Format3164::with_location`.
+    pub fn location(self) -> Self {
+        let mut s = self;
+        s.format = s.format.with_location();
+        s
+    }
+
+    /// Limit whole messages to `max` bytes
+    ///
+    /// See `Format3164::with_max_len`.
+    pub fn max_len(self, max: usize) -> Self {
+        let mut s = self;
+        s.format = s.format.with_max_len(max);
+        s
+    }
+
+    /// Text appended to values and messages cut by a limit
+    ///
+    /// See `Format3164::with_truncation_marker`.
+    pub fn truncation_marker<M: Into<String>>(self, marker: M) -> Self {
+        let mut s = self;
+        s.format = s.format.with_truncation_marker(marker);
+        s
+    }
+
+    /// Limit values of `key` to `max` bytes
+    ///
+    /// See `Format3164::with_value_limit`.
+    pub fn value_limit<K: Into<String>>(self, key: K, max: usize) -> Self {
+        let mut s = self;
+        s.format = s.format.with_value_limit(key, max);
+        s
+    }
+
+    /// Send a fixed message in place of records whose formatting panics
+    pub fn catch_panics(self) -> Self {
+        let mut s = self;
+        s.catch_panics = true;
+        s
+    }
+
+    /// Write records at or above `min_level` that fail to `/dev/console`
+    ///
+    /// The original error is still returned. Skipped if the console can't
+    /// be opened.
+    pub fn console_fallback(self, min_level: slog::Level) -> Self {
+        let mut s = self;
+        s.console_fallback = Some(min_level);
+        s
+    }
+
+    /// Reserve `capacity` bytes in the per-thread formatting buffer
+    pub fn buffer_capacity(self, capacity: usize) -> Self {
+        let mut s = self;
+        s.buffer_capacity = capacity;
+        s
+    }
+
+    /// Don't warn on stderr when the facility is `LOG_KERN`
+    pub fn no_kern_warning(self) -> Self {
+        let mut s = self;
+        s.kern_warning = false;
+        s
+    }
+
+    /// Replace repeats of a message with `last message repeated N times`
+    ///
+    /// The count goes out before the next different message, before a
+    /// repeat arriving `timeout` after the last send, and when the drain
+    /// is dropped. With `start_async` it also goes out once `timeout` has
+    /// passed.
+    pub fn dedup(self, timeout: Duration) -> Self {
+        let mut s = self;
+        s.dedup = Some(timeout);
+        s
+    }
+
+    /// Only send one in `keep_one_in` records at or below `level`
+    pub fn sample(self, level: slog::Level, keep_one_in: u32) -> Self {
+        let mut s = self;
+        s.sample = Some((level, keep_one_in));
+        s
+    }
+
+    /// Remote UDP syslogging
+    pub fn udp<S: AsRef<str>>(self, local: SocketAddr, host: SocketAddr, hostname: S) -> Self {
+        let hostname = hostname.as_ref().to_string();
+        self.transports(vec![TransportSpec::Udp {
+            local,
+            host,
+            hostname,
+        }])
+    }
+
+    /// `connect()` the UDP socket, so that a missing collector is reported
+    ///
+    /// Only supported on Linux; elsewhere `start` fails with
+    /// `ErrorKind::Unsupported`.
+    pub fn udp_connected(self) -> Self {
+        let mut s = self;
+        s.udp_connected = true;
+        s
+    }
+
+    /// Remote TCP syslogging
+    pub fn tcp<S: AsRef<str>>(self, server: SocketAddr, hostname: S) -> Self {
+        let hostname = hostname.as_ref().to_string();
+        self.transports(vec![TransportSpec::Tcp { server, hostname }])
+    }
+
+    /// Enable TCP keepalive, probing after `idle` without traffic
+    pub fn tcp_keepalive(self, idle: Duration) -> Self {
+        let mut s = self;
+        s.tcp.keepalive = Some(idle);
+        s
+    }
+
+    /// Fail TCP writes that block for longer than `timeout`
+    pub fn tcp_write_timeout(self, timeout: Duration) -> Self {
+        let mut s = self;
+        s.tcp.write_timeout = Some(timeout);
+        s
+    }
+
+    /// Give up connecting over TCP after `timeout`
+    pub fn tcp_connect_timeout(self, timeout: Duration) -> Self {
+        let mut s = self;
+        s.tcp.connect_timeout = Some(timeout);
+        s
+    }
+
+    /// Delimit messages on TCP and unix stream sockets with `framing`
+    ///
+    /// TCP sends messages back to back by default; unix stream sockets
+    /// default to `NonTransparent`.
+    pub fn tcp_framing(self, framing: TcpFraming) -> Self {
+        let mut s = self;
+        s.tcp.framing = Some(framing);
+        s
+    }
+
+    /// Reconnect over TCP when a write fails
+    ///
+    /// Makes up to `attempts` connections, waiting `backoff` before the
+    /// second and doubling the wait after that, up to 30 seconds. Each
+    /// attempt times out after `tcp_connect_timeout`, or one second.
+    pub fn tcp_reconnect(self, attempts: u32, backoff: Duration) -> Self {
+        let mut s = self;
+        s.tcp.reconnect_attempts = attempts;
+        s.tcp.reconnect_backoff = backoff;
+        s
+    }
+
+    /// Local syslogging over a unix socket
+    pub fn unix<P: AsRef<Path>>(self, path: P) -> Self {
+        let path = path.as_ref().to_path_buf();
+        self.transports(vec![TransportSpec::Unix { path }])
+    }
+
+    /// Local syslogging over a `SOCK_STREAM` unix socket
+    pub fn unix_stream<P: AsRef<Path>>(self, path: P) -> Self {
+        let path = path.as_ref().to_path_buf();
+        self.transports(vec![TransportSpec::UnixStream { path }])
+    }
+
+    /// Local syslogging over the first unix socket found
+    ///
+    /// See `TransportSpec::UnixAuto` for the paths that are tried.
+    pub fn unix_auto(self) -> Self {
+        self.transports(vec![TransportSpec::UnixAuto])
+    }
+
+    /// Try several transports in order, using the first that connects
+    pub fn transports(self, transports: Vec<TransportSpec>) -> Self {
+        let mut s = self;
+        s.transports = transports;
+        s
+    }
+
+    /// Connect over `transport`, also returning the framer of a stream
+    /// socket, if it needs one
+    fn connect(&self, facility: syslog::Facility, transport: &TransportSpec) -> io::Result<(SysLogger, Option<Framer>)> {
+        let unix_framing = self.tcp.framing.unwrap_or(TcpFraming::NonTransparent);
+        let log = match *transport {
+            TransportSpec::Unix { ref path } => {
+                let format = syslog_format3164(facility, None);
+                return unix_connect(format, path, unix_framing);
+            }
+            TransportSpec::UnixStream { ref path } => {
+                let format = syslog_format3164(facility, None);
+                let (log, framer) = unix_stream(format, path, unix_framing)?;
+                return Ok((log, Some(framer)));
+            }
+            TransportSpec::UnixAuto => return unix_auto(facility, unix_framing),
+            TransportSpec::Udp {
+                local,
+                host,
+                ref hostname,
+            } => {
+                let format = syslog_format3164(facility, Some(hostname.clone()));
+                if self.udp_connected {
+                    udp_connected(format, local, host)?
+                } else {
+                    syslog::udp(format, local, host).map_err(handle_syslog_error)?
+                }
+            },
+            TransportSpec::Tcp { server, ref hostname } => {
+                let format = syslog_format3164(facility, Some(hostname.clone()));
+                let (log, stream) = tcp_connect(format.clone(), server, &self.tcp)?;
+                let framer = self
+                    .tcp
+                    .framing
+                    .map(|framing| Framer::new(framing, format, FramedStream::Tcp(stream)));
+                return Ok((log, framer));
+            },
+        };
+        Ok((log, None))
+    }
+
+    /// Connect to the first of `self.transports` that works
+    fn connect_any(&self, facility: syslog::Facility) -> io::Result<(SysLogger, Option<Framer>, &TransportSpec)> {
+        let mut errors = Vec::new();
+        for transport in &self.transports {
+            match self.connect(facility, transport) {
+                Ok((log, framer)) => return Ok((log, framer, transport)),
+                Err(e) => errors.push((transport, e)),
+            }
+        }
+        if errors.len() == 1 {
+            return Err(errors.pop().unwrap().1);
+        }
+        let msg = errors
+            .iter()
+            .map(|(transport, e)| format!("{}: {}", transport, e))
+            .collect::<Vec<_>>()
+            .join("; ");
+        Err(Error::other(format!("no syslog transport could be started: {}", msg)))
+    }
+
+    /// Start running, writing to syslog from a worker thread
+    ///
+    /// See `AsyncStreamer3164`.
+    pub fn start_async(self, capacity: usize, overflow: OverflowPolicy) -> io::Result<AsyncStreamer3164> {
+        AsyncStreamer3164::new(self.start()?, capacity, overflow)
+    }
+
+    /// Start running, also returning a handle to change the level later
+    pub fn start_with_level_handle(self) -> io::Result<(Streamer3164, LevelHandle)> {
+        let streamer = self.start()?;
+        let handle = streamer.level_handle();
+        Ok((streamer, handle))
+    }
+
+    /// Start running
+    pub fn start(self) -> io::Result<Streamer3164> {
+        let facility = match self.facility {
+            Option::Some(Facility::LOG_AUTH) if self.prefer_authpriv => Facility::LOG_AUTHPRIV,
+            Option::Some(x) => x,
+            Option::None => {
+                return Err(Error::other(
+                    "facility must be provided to the builder",
+                ));
+            }
+        };
+        if self.kern_warning {
+            warn_if_kern(facility);
+        }
+        if self.transports.is_empty() {
+            return Err(Error::other(
+                "no logger kind provided, library does not know what do initialize",
+            ));
+        }
+        let (log, framer, transport) = self.connect_any(facility)?;
+        let reconnect = match *transport {
+            TransportSpec::Tcp { server, ref hostname } if self.tcp.reconnect_attempts > 0 => {
+                let mut options = self.tcp.clone();
+                options.connect_timeout.get_or_insert(RECONNECT_TIMEOUT);
+                Some(Reconnect {
+                    facility,
+                    server,
+                    hostname: hostname.clone(),
+                    options,
+                })
+            }
+            _ => None,
+        };
+        let mut streamer = Streamer3164::new_with_level(Box::new(log), self.level);
+        streamer.format = self.format;
+        streamer.sampling = self.sample.map(|(level, keep_one_in)| Sampling {
+            level,
+            keep_one_in,
+            counter: AtomicU32::new(0),
+        });
+        streamer.buffer_capacity = self.buffer_capacity;
+        streamer.console_fallback = self.console_fallback;
+        streamer.catch_panics = self.catch_panics;
+        streamer.sidecar = self.sidecar.map(|io| Sidecar {
+            io: Mutex::new(io),
+            facility,
+        });
+        streamer.reconnect = reconnect;
+        streamer.framer = framer;
+        streamer.dedup = self.dedup.map(Dedup::new);
+        Ok(streamer)
+    }
+}
+
+/// `Streamer` to Unix syslog using RFC 3164 format
+pub fn unix_3164_with_level(facility: syslog::Facility, level: Level) -> io::Result<Streamer3164> {
+    warn_if_kern(facility);
+    let format = syslog_format3164(facility, None);
+    syslog::unix(format)
+        .map(Box::new)
+        .map(|logger| Streamer3164::new_with_level(logger, level))
+        .map_err(handle_syslog_error)
+}
+
+/// `Streamer` to Unix syslog using RFC 3164 format
+pub fn unix_3164(facility: syslog::Facility) -> io::Result<Streamer3164> {
+    unix_3164_with_level(facility, get_default_level())
+}
+
+/// Root `Logger` sending to the local syslog using RFC 3164 format
+///
+/// Like `unix_3164` wrapped in a fused root `Logger` with no key-values,
+/// with `ident` as the tag of every message instead of the executable
+/// name. Use `SyslogBuilder` for anything more involved.
+pub fn init(facility: syslog::Facility, ident: &str) -> io::Result<slog::Logger> {
+    init_with(facility, ident, syslog::unix)
+}
+
+/// `init`, connecting with `connect`
+fn init_with<C>(facility: syslog::Facility, ident: &str, connect: C) -> io::Result<slog::Logger>
+where
+    C: FnOnce(syslog::Formatter3164) -> syslog::Result<SysLogger>,
+{
+    warn_if_kern(facility);
+    let mut format = syslog_format3164(facility, None);
+    format.process = ident.to_string();
+    let log = connect(format).map_err(handle_syslog_error)?;
+    let drain = Streamer3164::new(Box::new(log));
+    Ok(slog::Logger::root(drain.fuse(), slog::o!()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static LOCATION: slog::RecordLocation = slog::RecordLocation {
+        file: file!(),
+        line: line!(),
+        column: 0,
+        function: "",
+        module: module_path!(),
+    };
+
+    /// Call `f` with a record at `level` carrying `msg` and `kv`
+    fn with_record<R>(level: Level, msg: &str, kv: slog::BorrowedKV, f: impl FnOnce(&Record) -> R) -> R {
+        let rs = slog::RecordStatic {
+            location: &LOCATION,
+            tag: "",
+            level,
+        };
+        f(&Record::new(&rs, &format_args!("{}", msg), kv))
+    }
+
+    /// Format an info record with `format`
+    fn format_with(format: &Format3164, msg: &str, kv: slog::BorrowedKV) -> String {
+        let values = OwnedKVList::from(slog::o!());
+        with_record(Level::Info, msg, kv, |r| {
+            let mut out = String::new();
+            format.format(&mut out, r, &values).unwrap();
+            out
+        })
+    }
+
+    /// Local UDP socket standing in for a collector
+    fn udp_collector() -> UdpSocket {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        socket
+    }
+
+    fn recv_string(socket: &UdpSocket) -> String {
+        let mut buf = [0; 2048];
+        let len = socket.recv(&mut buf).unwrap();
+        String::from_utf8(buf[..len].to_vec()).unwrap()
+    }
+
+    fn udp_builder(collector: &UdpSocket) -> SyslogBuilder {
+        SyslogBuilder::new()
+            .facility(Facility::LOG_USER)
+            .udp("127.0.0.1:0".parse().unwrap(), collector.local_addr().unwrap(), "host")
+    }
+
+    #[test]
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn udp_connected_reports_missing_collector() {
+        let builder = udp_builder(&udp_collector()).udp_connected();
+        let drain = builder.start().unwrap();
+        let values = OwnedKVList::from(slog::o!());
+        // The port-unreachable reply to one send fails a later one
+        let error = (0..50)
+            .find_map(|_| {
+                thread::sleep(Duration::from_millis(10));
+                with_record(Level::Error, "anyone there?", slog::b!(), |r| drain.log(r, &values)).err()
+            })
+            .expect("no error from a missing collector");
+        assert_eq!(error.kind(), io::ErrorKind::ConnectionRefused);
+    }
+
+    #[test]
+    fn transports_fall_back_to_the_next_one() {
+        let collector = udp_collector();
+        let drain = SyslogBuilder::new()
+            .facility(Facility::LOG_USER)
+            .transports(vec![
+                TransportSpec::Unix {
+                    path: PathBuf::from("/nonexistent/slog-syslog.sock"),
+                },
+                TransportSpec::Udp {
+                    local: "127.0.0.1:0".parse().unwrap(),
+                    host: collector.local_addr().unwrap(),
+                    hostname: "host".to_string(),
+                },
+            ])
+            .start()
+            .unwrap();
+        let values = OwnedKVList::from(slog::o!());
+        with_record(Level::Info, "over udp", slog::b!(), |r| drain.log(r, &values)).unwrap();
+        assert!(recv_string(&collector).ends_with(": over udp"));
+    }
+
+    #[test]
+    fn transports_list_every_failure() {
+        let error = SyslogBuilder::new()
+            .facility(Facility::LOG_USER)
+            .transports(vec![
+                TransportSpec::Unix {
+                    path: PathBuf::from("/nonexistent/a.sock"),
+                },
+                TransportSpec::UnixStream {
+                    path: PathBuf::from("/nonexistent/b.sock"),
+                },
+            ])
+            .start()
+            .err()
+            .unwrap();
+        let message = error.to_string();
+        assert!(message.contains("/nonexistent/a.sock"), "{}", message);
+        assert!(message.contains("/nonexistent/b.sock"), "{}", message);
+    }
+
+    /// Fresh directory under the system temp dir, removed on drop
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("slog-syslog-{}-{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&path);
+            std::fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn unix_auto_skips_directories_and_non_sockets() {
+        use std::os::unix::net::UnixDatagram;
+
+        let dir = TempDir::new("unix-auto");
+        // Like /var/run/log under systemd-journald
+        let log_dir = dir.0.join("log");
+        std::fs::create_dir(&log_dir).unwrap();
+        let file = dir.0.join("file");
+        std::fs::write(&file, b"").unwrap();
+        let socket_path = dir.0.join("socket");
+        let socket = UnixDatagram::bind(&socket_path).unwrap();
+        socket.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let missing = dir.0.join("missing");
+
+        let candidates = [missing.as_path(), log_dir.as_path(), file.as_path(), socket_path.as_path()];
+        let (mut log, framer) = unix_auto_from(Facility::LOG_USER, &candidates, TcpFraming::NonTransparent).unwrap();
+        assert!(framer.is_none());
+        log_with_level(Level::Info, &mut log, "found it").unwrap();
+        let mut buf = [0; 1024];
+        let len = socket.recv(&mut buf).unwrap();
+        assert!(String::from_utf8_lossy(&buf[..len]).ends_with(": found it"));
+
+        let error = unix_auto_from(Facility::LOG_USER, &candidates[..3], TcpFraming::NonTransparent).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+        let message = error.to_string();
+        assert!(message.contains(&format!("{}: missing", missing.display())), "{}", message);
+        assert!(message.contains(&format!("{}: directory", log_dir.display())), "{}", message);
+        assert!(message.contains(&format!("{}: not a socket", file.display())), "{}", message);
+    }
+
+    #[test]
+    fn sampling_keeps_one_in_n_and_never_drops_severe_records() {
+        let sampling = Sampling {
+            level: Level::Debug,
+            keep_one_in: 4,
+            counter: AtomicU32::new(0),
+        };
+        let mut kept = 0;
+        for _ in 0..100 {
+            if sampling.keep(Level::Debug) {
+                kept += 1;
+            }
+            if sampling.keep(Level::Trace) {
+                kept += 1;
+            }
+            assert!(sampling.keep(Level::Info));
+            assert!(sampling.keep(Level::Critical));
+        }
+        // Debug and Trace share one counter
+        assert_eq!(kept, 50);
+
+        let off = Sampling {
+            level: Level::Trace,
+            keep_one_in: 1,
+            counter: AtomicU32::new(0),
+        };
+        assert!((0..10).all(|_| off.keep(Level::Trace)));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn init_logs_with_ident_at_the_default_level() {
+        use std::os::unix::net::UnixDatagram;
+
+        let dir = TempDir::new("init");
+        let path = dir.0.join("log");
+        let socket = UnixDatagram::bind(&path).unwrap();
+        socket.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+        let log = init_with(Facility::LOG_USER, "myapp", |format| syslog::unix_custom(format, &path)).unwrap();
+        slog::info!(log, "smoke test");
+
+        let mut buf = [0; 1024];
+        let len = socket.recv(&mut buf).unwrap();
+        let message = String::from_utf8_lossy(&buf[..len]);
+        assert!(message.starts_with("<13>"), "{}", message);
+        let tag = format!(" myapp[{}]: ", std::process::id());
+        assert!(message.ends_with(&format!("{}smoke test", tag)), "{}", message);
+    }
+
+    #[test]
+    fn init_connects_to_the_local_syslog() {
+        let local_socket = ["/dev/log", "/var/run/syslog"].iter().any(|path| Path::new(path).exists());
+        match init(Facility::LOG_USER, "slog-syslog-test") {
+            Ok(log) => slog::info!(log, "init smoke test"),
+            Err(e) => assert!(!local_socket, "{}", e),
+        }
+    }
+
+    #[test]
+    fn limits_apply_on_their_own() {
+        let values = Format3164::new().with_limits(Limits {
+            max_value_len: Some(3),
+            ..Limits::default()
+        });
+        assert_eq!(format_with(&values, "msg", slog::b!("k" => "abcdef")), "msg, k: abc…trunc");
+        assert_eq!(format_with(&values, "msg", slog::b!("k" => "abc")), "msg, k: abc");
+
+        let keys = Format3164::new().with_limits(Limits {
+            max_keys: Some(1),
+            ..Limits::default()
+        });
+        assert_eq!(format_with(&keys, "msg", slog::b!("a" => 1)), "msg, a: 1");
+        // `b!` serializes its pairs last to first
+        assert_eq!(format_with(&keys, "msg", slog::b!("a" => 1, "b" => 2)), "msg, b: 2…trunc");
+
+        let bytes = Format3164::new().with_limits(Limits {
+            max_bytes: Some(10),
+            ..Limits::default()
+        });
+        assert_eq!(format_with(&bytes, "0123456789", slog::b!()), "0123456789");
+        assert_eq!(format_with(&bytes, "0123456789abcdef", slog::b!()), "01…trunc");
+    }
+
+    #[test]
+    fn limits_apply_together() {
+        let format = Format3164::new().with_limits(Limits {
+            max_bytes: Some(30),
+            max_keys: Some(2),
+            max_value_len: Some(4),
+        });
+        // Values are cut to 4 bytes and `a` is dropped, which leaves
+        // `message, c: cc, b: bbbb…trunc`; that is then clamped to 30
+        // bytes including the marker.
+        let out = format_with(&format, "message", slog::b!("a" => "aaaaaaaa", "b" => "bbbbbbbb", "c" => "cc"));
+        assert_eq!(out, "message, c: cc, b: bbb…trunc");
+        assert_eq!(out.len(), 30);
+    }
+
+    #[test]
+    fn value_limits_override_the_default_per_key() {
+        let format = Format3164::new()
+            .with_limits(Limits {
+                max_value_len: Some(3),
+                ..Limits::default()
+            })
+            .with_value_limit("stacktrace", 8)
+            .with_value_limit("id", 1);
+        let out = format_with(&format, "msg", slog::b!("id" => "42", "stacktrace" => "0123456789", "other" => "abcdef"));
+        assert_eq!(out, "msg, other: abc…trunc, stacktrace: 01234567…trunc, id: 4…trunc");
+        let out = format_with(&format, "msg", slog::b!("stacktrace" => "01234567", "other" => "abc"));
+        assert_eq!(out, "msg, other: abc, stacktrace: 01234567");
+
+        // Dropping pairs still marks the end of the message
+        let format = format.with_limits(Limits {
+            max_keys: Some(1),
+            max_value_len: Some(3),
+            ..Limits::default()
+        });
+        let out = format_with(&format, "msg", slog::b!("a" => 1, "other" => "abcdef"));
+        assert_eq!(out, "msg, other: abc…trunc…trunc");
+    }
+
+    #[test]
+    fn max_len_cuts_before_multibyte_characters() {
+        let format = Format3164::new().with_max_len(10).with_truncation_marker("[t]");
+        // Room for 7 bytes before the marker; `é` would span bytes 6..8
+        assert_eq!(format_with(&format, "abcdeféxyz", slog::b!()), "abcdef[t]");
+        // `é` ends exactly at byte 7
+        assert_eq!(format_with(&format, "abcdeéxyzw", slog::b!()), "abcdeé[t]");
+        // A 4-byte character spanning bytes 5..9
+        assert_eq!(format_with(&format, "abcde🦀xyz", slog::b!()), "abcde[t]");
+        // Exactly 10 bytes fits without a marker
+        assert_eq!(format_with(&format, "abcdefghé", slog::b!()), "abcdefghé");
+    }
+
+    #[test]
+    fn max_len_uses_the_default_marker() {
+        let format = Format3164::new().with_max_len(12);
+        let out = format_with(&format, "ééééééé", slog::b!());
+        assert_eq!(out, "éé…trunc");
+        assert!(out.len() <= 12);
+    }
+
+    #[test]
+    fn max_len_holds_with_a_marker_longer_than_it() {
+        let format = Format3164::new().with_max_len(4).with_truncation_marker("[truncated]");
+        assert_eq!(format_with(&format, "hello", slog::b!()), "[tru");
+        let format = Format3164::new().with_max_len(4);
+        assert_eq!(format_with(&format, "hello", slog::b!()), "…t");
+    }
+
+    /// Listener on loopback whose accept queue is full, so that further
+    /// connection attempts hang until they time out
+    #[cfg(target_os = "linux")]
+    fn unreachable_tcp_server() -> (SocketAddr, Vec<TcpStream>, std::net::TcpListener) {
+        use std::os::unix::io::AsRawFd;
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        // Shrink the backlog, which `std` always sets to 128
+        let ret = unsafe { libc::listen(listener.as_raw_fd(), 0) };
+        assert_eq!(ret, 0);
+        let addr = listener.local_addr().unwrap();
+        let mut fillers = Vec::new();
+        while fillers.len() < 16 {
+            match TcpStream::connect_timeout(&addr, Duration::from_millis(200)) {
+                Ok(stream) => fillers.push(stream),
+                Err(e) if e.kind() == io::ErrorKind::TimedOut => return (addr, fillers, listener),
+                Err(e) => panic!("{}", e),
+            }
+        }
+        panic!("accept queue never filled up");
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn tcp_connect_timeout_fires() {
+        let (addr, _fillers, _listener) = unreachable_tcp_server();
+        let started = std::time::Instant::now();
+        let error = SyslogBuilder::new()
+            .facility(Facility::LOG_USER)
+            .tcp(addr, "host")
+            .tcp_connect_timeout(Duration::from_millis(200))
+            .start()
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    /// Writer appending to a buffer shared with the test
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Writer failing every write
+    struct BrokenWriter;
+
+    impl io::Write for BrokenWriter {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(Error::other("sidecar broken"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn sidecar_gets_a_json_line_per_syslog_message() {
+        let collector = udp_collector();
+        let sidecar = SharedBuf::default();
+        let drain = udp_builder(&collector).structured_sidecar(sidecar.clone()).start().unwrap();
+        let values = OwnedKVList::from(slog::o!("app" => "test"));
+        with_record(Level::Warning, "disk \"full\"", slog::b!("free" => 0), |r| drain.log(r, &values)).unwrap();
+
+        assert!(recv_string(&collector).ends_with(": disk \"full\", app: test, free: 0"));
+        let json = String::from_utf8(sidecar.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(json, "{\"msg\":\"disk \\\"full\\\"\",\"priority\":12,\"app\":\"test\",\"free\":\"0\"}\n");
+    }
+
+    #[test]
+    fn sidecar_errors_are_not_delivery_failures() {
+        let collector = udp_collector();
+        let drain = udp_builder(&collector).structured_sidecar(BrokenWriter).start().unwrap();
+        let values = OwnedKVList::from(slog::o!());
+        let error = with_record(Level::Info, "hello", slog::b!(), |r| drain.log(r, &values)).err().unwrap();
+
+        assert_eq!(error.to_string(), "sidecar broken");
+        assert!(recv_string(&collector).ends_with(": hello"));
+        assert!(drain.last_error().is_none());
+    }
+
+    #[test]
+    fn static_fields_follow_record_key_values() {
+        let format = Format3164::new()
+            .with_static_field("deployment", "eu-1\nprod")
+            .with_static_field("tenant", "acme")
+            .with_escaped_control_chars();
+        assert_eq!(
+            format_with(&format, "started", slog::b!("port" => 8080)),
+            "started, port: 8080, deployment: eu-1\\x0aprod, tenant: acme"
+        );
+    }
+
+    #[test]
+    fn escaped_control_chars_neutralize_crlf_and_ansi_escapes() {
+        let format = Format3164::new().with_escaped_control_chars();
+        let msg = "login failed\r\nMar  1 00:00:00 host sshd: accepted \x1b[2Jroot";
+        let out = format_with(&format, msg, slog::b!("user" => "\x1b]0;pwned\x07eve\r\n", "note" => "a\tb\u{85}"));
+        assert_eq!(
+            out,
+            "login failed\\x0d\\x0aMar  1 00:00:00 host sshd: accepted \\x1b[2Jroot, \
+             note: a\tb\\x85, user: \\x1b]0;pwned\\x07eve\\x0d\\x0a"
+        );
+        assert!(!out.contains(|c: char| c.is_control() && c != '\t'));
+
+        // Off by default
+        let out = format_with(&Format3164::new(), "a\r\nb", slog::b!("k" => "\x1b[0m"));
+        assert_eq!(out, "a\r\nb, k: \x1b[0m");
+    }
+
+    #[test]
+    fn location_follows_the_key_values() {
+        let format = Format3164::new().with_location();
+        let values = OwnedKVList::from(slog::o!());
+        let mut out = String::new();
+        let line = line!() + 1;
+        format.format(&mut out, &slog::record!(Level::Info, "", &format_args!("hello"), slog::b!("k" => 1)), &values).unwrap();
+        assert_eq!(out, format!("hello, k: 1 [src={}:{}:{}]", module_path!(), file!(), line));
+    }
+
+    #[test]
+    fn location_leaves_out_empty_parts() {
+        let format = Format3164::new().with_location();
+        let values = OwnedKVList::from(slog::o!());
+        let format_at = |location: &'static slog::RecordLocation| {
+            let rs = slog::RecordStatic {
+                location,
+                tag: "",
+                level: Level::Info,
+            };
+            let mut out = String::new();
+            format.format(&mut out, &Record::new(&rs, &format_args!("msg"), slog::b!()), &values).unwrap();
+            out
+        };
+        static NO_MODULE: slog::RecordLocation = slog::RecordLocation {
+            file: "lib.rs",
+            line: 7,
+            column: 0,
+            function: "",
+            module: "",
+        };
+        static NO_FILE_OR_LINE: slog::RecordLocation = slog::RecordLocation {
+            file: "",
+            line: 0,
+            column: 0,
+            function: "f",
+            module: "app::db",
+        };
+        static NOWHERE: slog::RecordLocation = slog::RecordLocation {
+            file: "",
+            line: 0,
+            column: 0,
+            function: "",
+            module: "",
+        };
+        assert_eq!(format_at(&NO_MODULE), "msg [src=lib.rs:7]");
+        assert_eq!(format_at(&NO_FILE_OR_LINE), "msg [src=app::db]");
+        assert_eq!(format_at(&NOWHERE), "msg");
+    }
+
+    #[test]
+    fn separators_replace_the_defaults() {
+        let default = Format3164::new();
+        assert_eq!(format_with(&default, "msg", slog::b!("b" => 2, "a" => "x y")), "msg, a: x y, b: 2");
+        let format = Format3164::new().with_separators("=", " ");
+        assert_eq!(format_with(&format, "msg", slog::b!("b" => 2, "a" => "x y")), "msg a=x y b=2");
+        assert_eq!(format_with(&format, "msg", slog::b!()), "msg");
+
+        let collector = udp_collector();
+        let drain = udp_builder(&collector).separators("=", " | ").start().unwrap();
+        let values = OwnedKVList::from(slog::o!("app" => "test"));
+        with_record(Level::Info, "msg", slog::b!("k" => 1), |r| drain.log(r, &values)).unwrap();
+        assert!(recv_string(&collector).ends_with(": msg | app=test | k=1"));
+    }
+
+    #[test]
+    fn static_fields_ignore_kv_min_level() {
+        let format = Format3164::new()
+            .with_static_field("deployment", "eu-1")
+            .with_kv_min_level(Level::Warning);
+        assert_eq!(format_with(&format, "started", slog::b!("port" => 8080)), "started, deployment: eu-1");
+    }
+
+    /// Accept one connection and read it until it's closed
+    fn read_connection(listener: &std::net::TcpListener) -> Vec<u8> {
+        use std::io::Read;
+
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn tcp_reconnects_after_the_server_closed_the_connection() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let drain = SyslogBuilder::new()
+            .facility(Facility::LOG_USER)
+            .tcp(listener.local_addr().unwrap(), "host")
+            .tcp_framing(TcpFraming::NonTransparent)
+            .tcp_reconnect(3, Duration::from_millis(10))
+            .start()
+            .unwrap();
+        drop(listener.accept().unwrap());
+
+        let values = OwnedKVList::from(slog::o!());
+        for msg in &["one", "two", "three"] {
+            with_record(Level::Info, msg, slog::b!(), |r| drain.log(r, &values)).unwrap();
+        }
+        drop(drain);
+        let received = String::from_utf8(read_connection(&listener)).unwrap();
+        assert!(received.ends_with(": three\n"), "{}", received);
+    }
+
+    #[test]
+    fn tcp_reconnect_is_off_by_default() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let drain = SyslogBuilder::new()
+            .facility(Facility::LOG_USER)
+            .tcp(listener.local_addr().unwrap(), "host")
+            .start()
+            .unwrap();
+        drop(listener.accept().unwrap());
+
+        let values = OwnedKVList::from(slog::o!());
+        let failed = (0..50).any(|_| {
+            thread::sleep(Duration::from_millis(10));
+            with_record(Level::Info, "lost", slog::b!(), |r| drain.log(r, &values)).is_err()
+        });
+        assert!(failed);
+        assert!(drain.reconnect.is_none());
+    }
+
+    #[test]
+    fn tcp_octet_counting_frames_hold_whole_messages() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let drain = SyslogBuilder::new()
+            .facility(Facility::LOG_USER)
+            .tcp(listener.local_addr().unwrap(), "host")
+            .tcp_framing(TcpFraming::OctetCounting)
+            .start()
+            .unwrap();
+
+        let values = OwnedKVList::from(slog::o!());
+        for msg in &["one", "two\nlines"] {
+            with_record(Level::Info, msg, slog::b!(), |r| drain.log(r, &values)).unwrap();
+        }
+        drop(drain);
+
+        let received = read_connection(&listener);
+        let mut frames = Vec::new();
+        let mut rest = &received[..];
+        while !rest.is_empty() {
+            let space = rest.iter().position(|&b| b == b' ').unwrap();
+            let len: usize = std::str::from_utf8(&rest[..space]).unwrap().parse().unwrap();
+            frames.push(String::from_utf8(rest[space + 1..space + 1 + len].to_vec()).unwrap());
+            rest = &rest[space + 1 + len..];
+        }
+        assert_eq!(frames.len(), 2, "{:?}", frames);
+        assert!(frames[0].starts_with("<13>") && frames[0].ends_with(": one"), "{}", frames[0]);
+        assert!(frames[1].starts_with("<13>") && frames[1].ends_with(": two\nlines"), "{}", frames[1]);
+    }
+
+    #[test]
+    fn tcp_non_transparent_frames_end_with_lf() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let drain = SyslogBuilder::new()
+            .facility(Facility::LOG_USER)
+            .tcp(listener.local_addr().unwrap(), "host")
+            .tcp_framing(TcpFraming::NonTransparent)
+            .start()
+            .unwrap();
+
+        let values = OwnedKVList::from(slog::o!());
+        for msg in &["one", "two"] {
+            with_record(Level::Warning, msg, slog::b!(), |r| drain.log(r, &values)).unwrap();
+        }
+        drop(drain);
+
+        let received = String::from_utf8(read_connection(&listener)).unwrap();
+        let frames: Vec<_> = received.split_terminator('\n').collect();
+        assert_eq!(frames.len(), 2, "{:?}", frames);
+        assert!(frames[0].starts_with("<12>") && frames[0].contains(" host "), "{}", frames[0]);
+        assert!(frames[0].ends_with(": one") && frames[1].ends_with(": two"), "{:?}", frames);
+    }
+
+    #[test]
+    fn dedup_counts_repeats_until_a_different_message() {
+        let collector = udp_collector();
+        let drain = udp_builder(&collector).dedup(Duration::from_secs(3600)).start().unwrap();
+        let values = OwnedKVList::from(slog::o!());
+        for msg in &["same", "same", "same", "other", "other"] {
+            with_record(Level::Info, msg, slog::b!(), |r| drain.log(r, &values)).unwrap();
+        }
+        assert!(recv_string(&collector).ends_with(": same"));
+        assert!(recv_string(&collector).ends_with(": last message repeated 2 times"));
+        assert!(recv_string(&collector).ends_with(": other"));
+        drop(drain);
+        assert!(recv_string(&collector).ends_with(": last message repeated 1 times"));
+    }
+
+    #[test]
+    fn async_dedup_sends_the_count_after_the_timeout() {
+        let collector = udp_collector();
+        let drain = udp_builder(&collector)
+            .dedup(Duration::from_millis(100))
+            .start_async(16, OverflowPolicy::Block)
+            .unwrap();
+        let values = OwnedKVList::from(slog::o!());
+        for _ in 0..3 {
+            with_record(Level::Info, "same", slog::b!(), |r| drain.log(r, &values)).unwrap();
+        }
+        assert!(recv_string(&collector).ends_with(": same"));
+        // No further record, the drain is still alive
+        assert!(recv_string(&collector).ends_with(": last message repeated 2 times"));
+        drop(drain);
+    }
+
+    #[test]
+    fn async_drop_policy_counts_records_discarded_while_stalled() {
+        let collector = udp_collector();
+        let sidecar = SharedBuf::default();
+        let drain = udp_builder(&collector)
+            .structured_sidecar(sidecar.clone())
+            .start_async(1, OverflowPolicy::Drop)
+            .unwrap();
+        let values = OwnedKVList::from(slog::o!());
+
+        // Stall the worker thread on the syslog connection
+        let stall = drain.inner.io.lock().unwrap();
+        for i in 0..10 {
+            with_record(Level::Info, &format!("record {}", i), slog::b!(), |r| drain.log(r, &values)).unwrap();
+        }
+        // One record is queued, and the worker may hold another one
+        let dropped = drain.dropped();
+        assert!(dropped == 8 || dropped == 9, "{}", dropped);
+        drop(stall);
+        drop(drain);
+
+        let sent = 10 - dropped as usize;
+        for i in 0..sent {
+            assert!(recv_string(&collector).ends_with(&format!(": record {}", i)));
+        }
+        let json = String::from_utf8(sidecar.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(json.lines().count(), sent);
+    }
+
+    #[test]
+    fn async_block_policy_loses_no_records() {
+        let collector = udp_collector();
+        let sidecar = SharedBuf::default();
+        let drain = Arc::new(
+            udp_builder(&collector)
+                .structured_sidecar(sidecar.clone())
+                .start_async(1, OverflowPolicy::Block)
+                .unwrap(),
+        );
+
+        let stall = drain.inner.io.lock().unwrap();
+        let logging = {
+            let drain = drain.clone();
+            thread::spawn(move || {
+                let values = OwnedKVList::from(slog::o!());
+                for i in 0..20 {
+                    with_record(Level::Info, &format!("record {}", i), slog::b!(), |r| drain.log(r, &values)).unwrap();
+                }
+            })
+        };
+        thread::sleep(Duration::from_millis(100));
+        assert!(!logging.is_finished());
+        drop(stall);
+        logging.join().unwrap();
+        assert_eq!(drain.dropped(), 0);
+        drop(Arc::try_unwrap(drain).ok().unwrap());
+
+        for i in 0..20 {
+            assert!(recv_string(&collector).ends_with(&format!(": record {}", i)));
+        }
+        let json = String::from_utf8(sidecar.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(json.lines().count(), 20);
+    }
+
+    #[test]
+    fn async_sidecar_skips_records_that_failed() {
+        let (mut streamer, _listener) = failing_streamer();
+        let sidecar = SharedBuf::default();
+        streamer.sidecar = Some(Sidecar {
+            io: Mutex::new(Box::new(sidecar.clone())),
+            facility: Facility::LOG_USER,
+        });
+        let drain = AsyncStreamer3164::new(streamer, 4, OverflowPolicy::Block).unwrap();
+        let values = OwnedKVList::from(slog::o!());
+        with_record(Level::Info, "lost", slog::b!(), |r| drain.log(r, &values)).unwrap();
+        drop(drain);
+
+        assert!(sidecar.0.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn unix_3164_warns_about_log_kern() {
+        // Whether or not a local syslog socket exists
+        let _ = unix_3164(Facility::LOG_KERN);
+        assert!(KERN_WARNED.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn buffer_capacity_presizes_the_thread_buffer() {
+        let collector = udp_collector();
+        let drain = udp_builder(&collector).buffer_capacity(4096).start().unwrap();
+        let capacity = || TL_BUF.with(|buf| buf.borrow().capacity());
+        // A fresh thread, so a fresh buffer
+        thread::spawn(move || {
+            let values = OwnedKVList::from(slog::o!());
+            assert!(capacity() < 4096);
+            with_record(Level::Info, "first", slog::b!(), |r| drain.log(r, &values)).unwrap();
+            let presized = capacity();
+            assert!(presized >= 4096, "{}", presized);
+
+            let long = "x".repeat(3000);
+            with_record(Level::Info, &long, slog::b!(), |r| drain.log(r, &values)).unwrap();
+            assert_eq!(capacity(), presized);
+        })
+        .join()
+        .unwrap();
+        assert!(recv_string(&collector).ends_with(": first"));
+    }
+
+    /// Drain whose every write fails with `BrokenPipe`, as long as the
+    /// returned listener is kept
+    fn failing_streamer() -> (Streamer3164, std::net::TcpListener) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        stream.shutdown(std::net::Shutdown::Write).unwrap();
+        let backend = syslog::LoggerBackend::Tcp(io::BufWriter::with_capacity(0, stream));
+        let format = syslog_format3164(Facility::LOG_USER, Some("host".to_string()));
+        let drain = Streamer3164::new_with_level(Box::new(syslog::Logger::new(backend, format)), Level::Trace);
+        (drain, listener)
+    }
+
+    #[test]
+    fn console_fallback_gets_records_that_failed() {
+        let dir = TempDir::new("console");
+        let console = dir.0.join("console");
+        std::fs::write(&console, b"").unwrap();
+        let (mut drain, _listener) = failing_streamer();
+        drain.console_fallback = Some(Level::Warning);
+        drain.console = console.clone();
+
+        let values = OwnedKVList::from(slog::o!());
+        let error = with_record(Level::Error, "lost", slog::b!("k" => 1), |r| drain.log(r, &values)).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::BrokenPipe);
+        // Below the fallback's level
+        with_record(Level::Info, "quiet", slog::b!(), |r| drain.log(r, &values)).unwrap_err();
+        assert_eq!(std::fs::read_to_string(&console).unwrap(), "lost, k: 1\n");
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn console_fallback_ignores_a_failing_console() {
+        let (mut drain, _listener) = failing_streamer();
+        drain.console_fallback = Some(Level::Warning);
+        // Every write to /dev/full fails with ENOSPC
+        drain.console = PathBuf::from("/dev/full");
+
+        let values = OwnedKVList::from(slog::o!());
+        let error = with_record(Level::Error, "lost", slog::b!(), |r| drain.log(r, &values)).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::BrokenPipe);
+        assert_eq!(drain.last_error().unwrap().kind, io::ErrorKind::BrokenPipe);
+    }
+
+    /// Value whose `Display` panics
+    struct Panicky;
+
+    impl fmt::Display for Panicky {
+        fn fmt(&self, _: &mut fmt::Formatter) -> fmt::Result {
+            panic!("Display of Panicky")
+        }
+    }
+
+    #[test]
+    fn catch_panics_sends_a_fallback_message() {
+        let collector = udp_collector();
+        let drain = udp_builder(&collector).catch_panics().start().unwrap();
+        let values = OwnedKVList::from(slog::o!());
+        let rs = slog::RecordStatic {
+            location: &LOCATION,
+            tag: "",
+            level: Level::Info,
+        };
+
+        drain.log(&Record::new(&rs, &format_args!("{}", Panicky), slog::b!()), &values).unwrap();
+        drain.log(&Record::new(&rs, &format_args!("fine"), slog::b!("k" => %Panicky)), &values).unwrap();
+        for _ in 0..2 {
+            let message = recv_string(&collector);
+            assert!(message.ends_with(": slog-syslog: panicked while formatting a log record"), "{}", message);
+        }
+    }
+
+    #[test]
+    fn catch_panics_skips_the_sidecar() {
+        let collector = udp_collector();
+        let sidecar = SharedBuf::default();
+        let drain = udp_builder(&collector).catch_panics().structured_sidecar(sidecar.clone()).start().unwrap();
+        let values = OwnedKVList::from(slog::o!());
+        let rs = slog::RecordStatic {
+            location: &LOCATION,
+            tag: "",
+            level: Level::Info,
+        };
+
+        drain.log(&Record::new(&rs, &format_args!("{}", Panicky), slog::b!()), &values).unwrap();
+        drain.log(&Record::new(&rs, &format_args!("fine"), slog::b!("k" => %Panicky)), &values).unwrap();
+        drain.log(&Record::new(&rs, &format_args!("fine"), slog::b!()), &values).unwrap();
+        for _ in 0..3 {
+            recv_string(&collector);
+        }
+        let json = String::from_utf8(sidecar.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(json, "{\"msg\":\"fine\",\"priority\":13}\n");
+    }
+
+    #[test]
+    fn custom_formats_can_be_installed() {
+        let collector = udp_collector();
+        let format = || Format3164::new().with_separators("=", " ").with_static_field("env", "prod");
+        let built = udp_builder(&collector).format(format()).start().unwrap();
+        let wrapped = udp_builder(&collector).start().unwrap().with_format(format());
+
+        let values = OwnedKVList::from(slog::o!());
+        for drain in &[built, wrapped] {
+            with_record(Level::Info, "msg", slog::b!("k" => 1), |r| drain.log(r, &values)).unwrap();
+            let message = recv_string(&collector);
+            assert!(message.ends_with(": msg k=1 env=prod"), "{}", message);
+        }
+    }
+
+    /// Read from `stream` until it holds `lines` LF-terminated frames
+    #[cfg(unix)]
+    fn read_lines(stream: &mut std::os::unix::net::UnixStream, lines: usize) -> String {
+        use std::io::Read;
+
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let mut received = Vec::new();
+        let mut buf = [0; 1024];
+        while received.iter().filter(|&&b| b == b'\n').count() < lines {
+            let len = stream.read(&mut buf).unwrap();
+            assert!(len > 0, "connection closed");
+            received.extend_from_slice(&buf[..len]);
+        }
+        String::from_utf8(received).unwrap()
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn unix_stream_frames_messages_with_lf() {
+        let dir = TempDir::new("unix-stream");
+        let path = dir.0.join("log");
+        let listener = std::os::unix::net::UnixListener::bind(&path).unwrap();
+        // `unix` falls back to a stream socket, `unix_stream` always uses one
+        for builder in [SyslogBuilder::new().unix(&path), SyslogBuilder::new().unix_stream(&path)] {
+            let drain = builder.facility(Facility::LOG_USER).start().unwrap();
+            let (mut stream, _) = listener.accept().unwrap();
+
+            let values = OwnedKVList::from(slog::o!());
+            for msg in &["one", "two"] {
+                with_record(Level::Info, msg, slog::b!(), |r| drain.log(r, &values)).unwrap();
+            }
+            // Read while the drain is alive: nothing may sit in a buffer
+            let received = read_lines(&mut stream, 2);
+            let frames: Vec<_> = received.split_terminator('\n').collect();
+            assert_eq!(frames.len(), 2, "{:?}", frames);
+            assert!(frames[0].starts_with("<13>") && frames[0].ends_with(": one"), "{:?}", frames);
+            assert!(frames[1].starts_with("<13>") && frames[1].ends_with(": two"), "{:?}", frames);
+            assert!(!received.contains('\0'), "{:?}", received);
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn unix_stream_can_use_octet_counting() {
+        use std::io::Read;
+
+        let dir = TempDir::new("unix-octets");
+        let path = dir.0.join("log");
+        let listener = std::os::unix::net::UnixListener::bind(&path).unwrap();
+        let drain = SyslogBuilder::new()
+            .facility(Facility::LOG_USER)
+            .unix_stream(&path)
+            .tcp_framing(TcpFraming::OctetCounting)
+            .start()
+            .unwrap();
+        let (mut stream, _) = listener.accept().unwrap();
+
+        let values = OwnedKVList::from(slog::o!());
+        with_record(Level::Info, "two\nlines", slog::b!(), |r| drain.log(r, &values)).unwrap();
+        drop(drain);
+        let mut received = String::new();
+        stream.read_to_string(&mut received).unwrap();
+        let (len, message) = received.split_once(' ').unwrap();
+        assert_eq!(len.parse::<usize>().unwrap(), message.len(), "{:?}", received);
+        assert!(message.ends_with(": two\nlines"), "{:?}", received);
+    }
+
+    #[test]
+    fn kv_min_level_keeps_key_values_from_that_level_up() {
+        let format = Format3164::new().with_kv_min_level(Level::Warning);
+        let values = OwnedKVList::from(slog::o!("service" => "api"));
+        let format_at = |level| {
+            with_record(level, "disk almost full", slog::b!("free" => "2%"), |r| {
+                let mut out = String::new();
+                format.format(&mut out, r, &values).unwrap();
+                out
+            })
+        };
+        let full = "disk almost full, service: api, free: 2%";
+        assert_eq!(format_at(Level::Critical), full);
+        assert_eq!(format_at(Level::Warning), full);
+        assert_eq!(format_at(Level::Info), "disk almost full");
+        assert_eq!(format_at(Level::Debug), "disk almost full");
+    }
+
+    #[test]
+    fn prefer_authpriv_moves_only_log_auth() {
+        let collector = udp_collector();
+        let send = |builder: SyslogBuilder, level| {
+            let drain = builder.udp("127.0.0.1:0".parse().unwrap(), collector.local_addr().unwrap(), "host").start().unwrap();
+            let values = OwnedKVList::from(slog::o!());
+            with_record(level, "login", slog::b!(), |r| drain.log(r, &values)).unwrap();
+            let message = recv_string(&collector);
+            message[..message.find('>').unwrap() + 1].to_string()
+        };
+        let auth = || SyslogBuilder::new().facility(Facility::LOG_AUTH);
+
+        // LOG_AUTH is 4, LOG_AUTHPRIV 10; notice is 5, warning 4
+        assert_eq!(send(auth(), Level::Info), "<37>");
+        assert_eq!(send(auth().prefer_authpriv(), Level::Info), "<85>");
+        assert_eq!(send(auth().prefer_authpriv(), Level::Warning), "<84>");
+        assert_eq!(send(SyslogBuilder::new().facility(Facility::LOG_USER).prefer_authpriv(), Level::Info), "<13>");
+    }
+
+    /// Value whose serialization fails
+    struct Unserializable;
+
+    impl slog::Value for Unserializable {
+        fn serialize(&self, _: &Record, _: slog::Key, _: &mut dyn slog::Serializer) -> slog::Result {
+            Err(slog::Error::Other)
+        }
+    }
+
+    #[test]
+    fn last_error_follows_the_most_recent_failure() {
+        let (drain, _listener) = failing_streamer();
+        assert!(drain.last_error().is_none());
+        let values = OwnedKVList::from(slog::o!());
+
+        with_record(Level::Info, "bad value", slog::b!("k" => Unserializable), |r| drain.log(r, &values)).unwrap_err();
+        let format_error = drain.last_error().unwrap();
+        assert_eq!(format_error.kind, io::ErrorKind::Other);
+
+        with_record(Level::Info, "lost", slog::b!(), |r| drain.log(r, &values)).unwrap_err();
+        let write_error = drain.last_error().unwrap();
+        assert_eq!(write_error.kind, io::ErrorKind::BrokenPipe);
+        assert_ne!(write_error.message, format_error.message);
+        assert!(write_error.time >= format_error.time);
+    }
+
+    #[test]
+    fn syslog_errors_keep_their_source_chain_and_io_kind() {
+        let e = syslog::Error::with_chain(Error::new(io::ErrorKind::ConnectionRefused, "refused"), syslog::ErrorKind::Write);
+        let e = handle_syslog_error(e);
+        assert_eq!(e.kind(), io::ErrorKind::ConnectionRefused);
+        let mut chain = Vec::new();
+        let mut source = e.get_ref().map(|e| e as &(dyn std::error::Error + 'static));
+        while let Some(err) = source {
+            chain.push(err.to_string());
+            source = err.source();
+        }
+        assert_eq!(chain.len(), 2, "{:?}", chain);
+        assert_eq!(chain[1], "refused");
+
+        let e = handle_syslog_error(syslog::Error::from(Error::new(io::ErrorKind::TimedOut, "timed out")));
+        assert_eq!(e.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(e.to_string(), "timed out");
+        let e = handle_syslog_error(syslog::ErrorKind::UnsupportedPlatform.into());
+        assert_eq!(e.kind(), io::ErrorKind::Unsupported);
+        let e = handle_syslog_error(syslog::ErrorKind::Initialization.into());
+        assert_eq!(e.kind(), io::ErrorKind::Other);
+        assert!(e.get_ref().unwrap().source().is_none());
+    }
+
+    #[test]
+    fn level_handle_changes_the_level_of_a_running_drain() {
+        let collector = udp_collector();
+        let (drain, handle) = udp_builder(&collector).level(Level::Info).start_with_level_handle().unwrap();
+        let log = slog::Logger::root(drain.fuse(), slog::o!());
+        let message = || {
+            let message = recv_string(&collector);
+            message[message.rfind(": ").unwrap() + 2..].to_string()
+        };
+
+        slog::debug!(log, "hidden");
+        slog::info!(log, "shown");
+        assert_eq!(message(), "shown");
+
+        // From a clone on another thread, while the logger is in use
+        let clone = handle.clone();
+        thread::spawn(move || clone.set(Level::Debug)).join().unwrap();
+        assert_eq!(handle.get(), Level::Debug);
+        slog::trace!(log, "hidden");
+        slog::debug!(log, "debug shown");
+        assert_eq!(message(), "debug shown");
+
+        let other = log.clone();
+        let clone = handle.clone();
+        thread::spawn(move || {
+            clone.set(Level::Error);
+            slog::warn!(other, "hidden");
+            slog::error!(other, "error shown");
+        })
+        .join()
+        .unwrap();
+        assert_eq!(message(), "error shown");
+        slog::warn!(log, "hidden");
+        slog::crit!(log, "crit shown");
+        assert_eq!(message(), "crit shown");
+    }
 }
@@ -0,0 +1,323 @@
+//! Rate limiting of records per severity
+
+use slog::{Drain, Level, OwnedKVList, Record};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Location reported for "N messages suppressed" records
+static SUPPRESSED_LOCATION: slog::RecordLocation = slog::RecordLocation {
+    file: file!(),
+    line: line!(),
+    column: 0,
+    function: "",
+    module: module_path!(),
+};
+
+/// Token bucket of one severity
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+    /// Records dropped since the last report
+    suppressed: u64,
+    last_report: Instant,
+}
+
+impl Bucket {
+    fn new(burst: u32) -> Self {
+        let now = Instant::now();
+        Bucket {
+            tokens: f64::from(burst),
+            last_refill: now,
+            suppressed: 0,
+            last_report: now,
+        }
+    }
+}
+
+/// Drain wrapper capping the number of records per second, per severity
+///
+/// Each severity has its own token bucket, holding up to `burst`
+/// records and refilled at `rate` records per second. A record that
+/// finds its bucket empty is dropped.
+///
+/// Drops are reported with a "N messages suppressed" record at the
+/// same severity, carrying a `suppressed` key. It is sent along with
+/// the next record of that severity once `report_interval` has passed
+/// since the previous report, whether that record is itself dropped or
+/// not.
+///
+/// ```
+/// use slog::Drain;
+/// use slog_syslog::RateLimited;
+///
+/// let drain = RateLimited::new(slog::Discard).rate(50.0).burst(200);
+/// let _log = slog::Logger::root(drain.fuse(), slog::o!());
+/// ```
+pub struct RateLimited<D: Drain> {
+    drain: D,
+    rate: f64,
+    burst: u32,
+    report_interval: Duration,
+    buckets: [Mutex<Bucket>; 6],
+    suppressed: AtomicU64,
+}
+
+impl<D: Drain> RateLimited<D> {
+    /// Wrap `drain`, allowing 100 records per second with bursts of 100
+    ///
+    /// Drops are reported every 10 seconds at most.
+    pub fn new(drain: D) -> Self {
+        let burst = 100;
+        RateLimited {
+            drain,
+            rate: 100.0,
+            burst,
+            report_interval: Duration::from_secs(10),
+            buckets: [
+                Mutex::new(Bucket::new(burst)),
+                Mutex::new(Bucket::new(burst)),
+                Mutex::new(Bucket::new(burst)),
+                Mutex::new(Bucket::new(burst)),
+                Mutex::new(Bucket::new(burst)),
+                Mutex::new(Bucket::new(burst)),
+            ],
+            suppressed: AtomicU64::new(0),
+        }
+    }
+
+    /// Refill every bucket at `per_second` records per second
+    ///
+    /// A rate of zero never refills the buckets. Panics if `per_second`
+    /// is negative, infinite or NaN.
+    pub fn rate(self, per_second: f64) -> Self {
+        assert!(
+            per_second.is_finite() && per_second >= 0.0,
+            "rate must be finite and not negative, got {}",
+            per_second
+        );
+        let mut s = self;
+        s.rate = per_second;
+        s
+    }
+
+    /// Let up to `burst` records through at once
+    ///
+    /// Buckets start out full.
+    pub fn burst(self, burst: u32) -> Self {
+        let mut s = self;
+        s.burst = burst;
+        for bucket in &mut s.buckets {
+            match bucket.get_mut() {
+                Ok(bucket) => *bucket = Bucket::new(burst),
+                Err(poisoned) => *poisoned.into_inner() = Bucket::new(burst),
+            }
+        }
+        s
+    }
+
+    /// Report drops at most once every `interval`
+    pub fn report_interval(self, interval: Duration) -> Self {
+        let mut s = self;
+        s.report_interval = interval;
+        s
+    }
+
+    /// Total number of records dropped so far
+    pub fn suppressed(&self) -> u64 {
+        self.suppressed.load(Ordering::Relaxed)
+    }
+
+    /// Take a token for `level`, also returning the number of drops to
+    /// report now, if any
+    fn acquire(&self, level: Level) -> (bool, Option<u64>) {
+        let mut bucket = match self.buckets[level.as_usize() - 1].lock() {
+            Ok(bucket) => bucket,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(f64::from(self.burst));
+        bucket.last_refill = now;
+
+        let allowed = bucket.tokens >= 1.0;
+        if allowed {
+            bucket.tokens -= 1.0;
+        } else {
+            bucket.suppressed += 1;
+            self.suppressed.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let report = if bucket.suppressed > 0 && now.duration_since(bucket.last_report) >= self.report_interval {
+            bucket.last_report = now;
+            Some(std::mem::replace(&mut bucket.suppressed, 0))
+        } else {
+            None
+        };
+        (allowed, report)
+    }
+}
+
+impl<D: Drain> Drain for RateLimited<D> {
+    type Ok = Option<D::Ok>;
+    type Err = D::Err;
+
+    fn log(&self, record: &Record, values: &OwnedKVList) -> Result<Self::Ok, Self::Err> {
+        let (allowed, report) = self.acquire(record.level());
+        if let Some(count) = report {
+            let rs = slog::RecordStatic {
+                location: &SUPPRESSED_LOCATION,
+                tag: record.tag(),
+                level: record.level(),
+            };
+            self.drain.log(
+                &Record::new(&rs, &format_args!("{} messages suppressed", count), slog::b!("suppressed" => count)),
+                values,
+            )?;
+        }
+        if allowed {
+            self.drain.log(record, values).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn is_enabled(&self, level: Level) -> bool {
+        self.drain.is_enabled(level)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    static LOCATION: slog::RecordLocation = slog::RecordLocation {
+        file: file!(),
+        line: line!(),
+        column: 0,
+        function: "",
+        module: module_path!(),
+    };
+
+    /// Drain keeping `LEVEL message key=value...` for every record
+    #[derive(Default)]
+    struct Capture(Mutex<Vec<String>>);
+
+    impl Capture {
+        fn take(&self) -> Vec<String> {
+            std::mem::take(&mut *self.0.lock().unwrap())
+        }
+    }
+
+    struct KeyValues(String);
+
+    impl slog::Serializer for KeyValues {
+        fn emit_arguments(&mut self, key: slog::Key, val: &std::fmt::Arguments) -> slog::Result {
+            self.0.push_str(&format!(" {}={}", key, val));
+            Ok(())
+        }
+    }
+
+    impl Drain for Capture {
+        type Ok = ();
+        type Err = slog::Never;
+
+        fn log(&self, record: &Record, _: &OwnedKVList) -> Result<(), slog::Never> {
+            use slog::KV;
+
+            let mut kv = KeyValues(String::new());
+            record.kv().serialize(record, &mut kv).unwrap();
+            let line = format!("{} {}{}", record.level().as_short_str(), record.msg(), kv.0);
+            self.0.lock().unwrap().push(line);
+            Ok(())
+        }
+    }
+
+    /// Log `msg` at `level`, returning whether it went through
+    fn log<D: Drain<Ok = (), Err = slog::Never>>(drain: &RateLimited<D>, level: Level, msg: &str) -> bool {
+        let rs = slog::RecordStatic {
+            location: &LOCATION,
+            tag: "",
+            level,
+        };
+        let values = OwnedKVList::from(slog::o!());
+        drain.log(&Record::new(&rs, &format_args!("{}", msg), slog::b!()), &values).unwrap().is_some()
+    }
+
+    #[test]
+    fn drops_records_once_the_bucket_is_empty() {
+        let capture = Capture::default();
+        let drain = RateLimited::new(&capture).rate(0.0).burst(3);
+
+        let passed: Vec<_> = (0..5).map(|i| log(&drain, Level::Info, &i.to_string())).collect();
+        assert_eq!(passed, [true, true, true, false, false]);
+        assert_eq!(capture.take(), ["INFO 0", "INFO 1", "INFO 2"]);
+        assert_eq!(drain.suppressed(), 2);
+    }
+
+    #[test]
+    fn refills_the_bucket_over_time() {
+        let capture = Capture::default();
+        let drain = RateLimited::new(&capture).rate(50.0).burst(1);
+
+        assert!(log(&drain, Level::Info, "first"));
+        assert!(!log(&drain, Level::Info, "dropped"));
+        thread::sleep(Duration::from_millis(100));
+        assert!(log(&drain, Level::Info, "refilled"));
+        assert_eq!(capture.take(), ["INFO first", "INFO refilled"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "rate must be finite and not negative")]
+    fn rejects_a_negative_rate() {
+        let _ = RateLimited::new(slog::Discard).rate(-1.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "rate must be finite and not negative")]
+    fn rejects_a_nan_rate() {
+        let _ = RateLimited::new(slog::Discard).rate(f64::NAN);
+    }
+
+    #[test]
+    #[should_panic(expected = "rate must be finite and not negative")]
+    fn rejects_an_infinite_rate() {
+        let _ = RateLimited::new(slog::Discard).rate(f64::INFINITY);
+    }
+
+    #[test]
+    fn severities_have_their_own_buckets() {
+        let capture = Capture::default();
+        let drain = RateLimited::new(&capture).rate(0.0).burst(1);
+
+        assert!(log(&drain, Level::Info, "info"));
+        assert!(!log(&drain, Level::Info, "info again"));
+        assert!(log(&drain, Level::Error, "error"));
+        assert!(log(&drain, Level::Debug, "debug"));
+        assert_eq!(capture.take(), ["INFO info", "ERRO error", "DEBG debug"]);
+    }
+
+    #[test]
+    fn reports_drops_after_the_report_interval() {
+        let capture = Capture::default();
+        let drain = RateLimited::new(&capture)
+            .rate(0.0)
+            .burst(1)
+            .report_interval(Duration::from_millis(200));
+
+        assert!(log(&drain, Level::Warning, "a"));
+        assert!(!log(&drain, Level::Warning, "b"));
+        assert!(!log(&drain, Level::Warning, "c"));
+        assert_eq!(capture.take(), ["WARN a"]);
+
+        thread::sleep(Duration::from_millis(250));
+        assert!(!log(&drain, Level::Warning, "d"));
+        assert_eq!(capture.take(), ["WARN 3 messages suppressed suppressed=3"]);
+
+        // Counting starts over after a report
+        assert!(!log(&drain, Level::Warning, "e"));
+        assert!(capture.take().is_empty());
+        assert_eq!(drain.suppressed(), 4);
+    }
+}